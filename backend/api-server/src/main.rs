@@ -2,6 +2,7 @@ use axum::http::{header, HeaderMap, HeaderValue};
 use axum::response::IntoResponse;
 use axum::{
     body::Bytes,
+    extract::DefaultBodyLimit,
     extract::Path,
     extract::Query,
     extract::State,
@@ -25,12 +26,15 @@ use tokio::net::TcpListener;
 
 // shared logic library
 use shared_logic::db::{
-    export_eeg_data_as_csv, get_earliest_eeg_timestamp, get_eeg_data_by_range,
+    export_eeg_data_as_brainvision, export_eeg_data_as_csv, export_eeg_data_as_edf,
+    export_eeg_data_as_xdf, get_earliest_eeg_timestamp, get_eeg_data_by_range,
     get_time_labels_by_range, initialize_connection, DbClient,
 };
 use shared_logic::models::{
-    EegDataQuery, EegDataRow, FrontendState, NewTimeLabel, NewUser, Session, TimeLabel,
+    ClockOffset, EegDataQuery, EegDataRow, FrontendState, NewTimeLabel, NewUser, PipelineChange,
+    PsdQuery, Session, StreamGap, TimeLabel,
 };
+use shared_logic::signal_processing::spectrum::{rows_power_spectrum, PowerSpectrum};
 
 // Argon2 imports
 use argon2::{
@@ -51,6 +55,9 @@ struct ExportEEGRequest {
     options: ExportOptions,
 }
 
+// formats accepted by export_eeg_data
+const EXPORT_FORMATS: [&str; 4] = ["csv", "xdf", "edf", "brainvision"];
+
 #[derive(Deserialize)]
 pub struct ExportOptions {
     format: String,
@@ -59,6 +66,15 @@ pub struct ExportOptions {
     end_time: Option<DateTime<Utc>>,
 }
 
+// query parameters of import_eeg_data, the format defaults to CSV
+#[derive(Deserialize)]
+struct ImportOptions {
+    format: Option<String>,
+}
+
+// largest file import_eeg_data accepts, hours of recording (axum's default is 2 MB)
+const MAX_IMPORT_BYTES: usize = 512 * 1024 * 1024;
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct LoginRequest {
     pub email: String,
@@ -371,8 +387,8 @@ async fn export_eeg_data(
         session_id
     );
 
-    // right now the only export format supported is CSV, so we just check for that
-    if request.options.format.to_lowercase() != "csv" {
+    let format = request.options.format.to_lowercase();
+    if !EXPORT_FORMATS.contains(&format.as_str()) {
         return Err((
             StatusCode::BAD_REQUEST,
             format!("Unsupported export format: {}", request.options.format),
@@ -384,17 +400,37 @@ async fn export_eeg_data(
 
     let header_included = request.options.include_header;
 
-    // finally call the export function in db.rs
-    let return_csv = match export_eeg_data_as_csv(
-        &app_state.db_client,
-        session_id,
-        start_time,
-        end_time,
-        header_included,
-    )
-    .await
-    {
-        Ok(csv_data) => csv_data,
+    // finally call the export function in db.rs for the requested format
+    let (exported, content_type) = match format.as_str() {
+        "xdf" => (
+            export_eeg_data_as_xdf(&app_state.db_client, session_id, start_time, end_time).await,
+            "application/octet-stream",
+        ),
+        "edf" => (
+            export_eeg_data_as_edf(&app_state.db_client, session_id, start_time, end_time).await,
+            "application/octet-stream",
+        ),
+        // the three BrainVision files come zipped together
+        "brainvision" => (
+            export_eeg_data_as_brainvision(&app_state.db_client, session_id, start_time, end_time)
+                .await,
+            "application/zip",
+        ),
+        _ => (
+            export_eeg_data_as_csv(
+                &app_state.db_client,
+                session_id,
+                start_time,
+                end_time,
+                header_included,
+            )
+            .await
+            .map(String::into_bytes),
+            "text/csv; charset=utf-8",
+        ),
+    };
+    let exported = match exported {
+        Ok(data) => data,
         Err(e) => {
             error!("Failed to export EEG data: {}", e);
             return Err((
@@ -408,10 +444,7 @@ async fn export_eeg_data(
     let filename = request.filename.replace('"', "");
 
     let mut headers = HeaderMap::new();
-    headers.insert(
-        header::CONTENT_TYPE,
-        HeaderValue::from_static("text/csv; charset=utf-8"),
-    );
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
 
     let content_disp = format!("attachment; filename=\"{}\"", filename);
     headers.insert(
@@ -424,8 +457,8 @@ async fn export_eeg_data(
         })?,
     );
 
-    // return CSV directly as the body
-    Ok((headers, return_csv))
+    // return the file directly as the body
+    Ok((headers, exported))
 }
 
 // Handler for POST /api/sessions/{session_id}/time-label
@@ -484,6 +517,60 @@ async fn get_time_labels(
     }
 }
 
+// Handler for GET /api/sessions/{session_id}/clock-offsets
+// Returns the LSL clock offsets that were applied to the session's EEG timestamps.
+async fn get_clock_offsets(
+    State(app_state): State<AppState>,
+    Path(session_id): Path<i32>,
+) -> Result<Json<Vec<ClockOffset>>, (StatusCode, String)> {
+    match shared_logic::db::get_clock_offsets(&app_state.db_client, session_id).await {
+        Ok(offsets) => Ok(Json(offsets)),
+        Err(e) => {
+            error!("Failed to get clock offsets: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to get clock offsets: {}", e),
+            ))
+        }
+    }
+}
+
+// Handler for GET /api/sessions/{session_id}/gaps
+// Returns the stretches of the session where the EEG stream was lost.
+async fn get_stream_gaps(
+    State(app_state): State<AppState>,
+    Path(session_id): Path<i32>,
+) -> Result<Json<Vec<StreamGap>>, (StatusCode, String)> {
+    match shared_logic::db::get_stream_gaps(&app_state.db_client, session_id).await {
+        Ok(gaps) => Ok(Json(gaps)),
+        Err(e) => {
+            error!("Failed to get stream gaps: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to get stream gaps: {}", e),
+            ))
+        }
+    }
+}
+
+// Handler for GET /api/sessions/{session_id}/pipeline-history
+// Returns every pipeline the session ran with and when each one took effect.
+async fn get_pipeline_history(
+    State(app_state): State<AppState>,
+    Path(session_id): Path<i32>,
+) -> Result<Json<Vec<PipelineChange>>, (StatusCode, String)> {
+    match shared_logic::db::get_pipeline_changes(&app_state.db_client, session_id).await {
+        Ok(changes) => Ok(Json(changes)),
+        Err(e) => {
+            error!("Failed to get pipeline history: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to get pipeline history: {}", e),
+            ))
+        }
+    }
+}
+
 // Handler for GET /api/sessions/{session_id}/eeg-data
 // Returns EEG data rows within a given time range (passed as ?start=...&end=... query params).
 async fn get_eeg_data(
@@ -511,21 +598,96 @@ async fn get_eeg_data(
     }
 }
 
-// Handler for POST /api/sessions/{session_id}/eeg_data/import
+// Handler for GET /api/sessions/{session_id}/psd
+// Returns the Welch power spectral density of each channel within a given time range
+// (?start=...&end=..., optionally &channels=0,2&segment_len=512&overlap=256&window=hann|hamming|rectangular).
+async fn get_psd(
+    State(app_state): State<AppState>,
+    Path(session_id): Path<i32>,
+    Query(params): Query<PsdQuery>,
+) -> Result<Json<PowerSpectrum>, (StatusCode, String)> {
+    info!(
+        "Received request for the PSD of session {} from {} to {}",
+        session_id, params.start, params.end
+    );
+
+    let channels = params
+        .channel_indices()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let rows =
+        match get_eeg_data_by_range(&app_state.db_client, session_id, params.start, params.end)
+            .await
+        {
+            Ok(rows) => rows,
+            Err(e) => {
+                error!("Failed to get EEG data: {}", e);
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to get EEG data: {}", e),
+                ));
+            }
+        };
+
+    // the PSD is CPU bound, keep it off the async workers
+    let config = params.welch_config();
+    let spectrum = tokio::task::spawn_blocking(move || {
+        rows_power_spectrum(&rows, channels.as_deref(), &config)
+    })
+    .await
+    .map_err(|e| {
+        error!("PSD task panicked: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to compute the PSD".to_string(),
+        )
+    })?;
+
+    match spectrum {
+        Ok(spectrum) => Ok(Json(spectrum)),
+        Err(e) => {
+            error!("Failed to compute the PSD: {}", e);
+            Err((StatusCode::BAD_REQUEST, e))
+        }
+    }
+}
+
+// Handler for POST /api/sessions/{session_id}/eeg_data/import?format=csv|xdf|edf
 async fn import_eeg_data(
     State(app_state): State<AppState>,
     Path(session_id): Path<i32>,
-    // we expect the CSV data to be sent as raw text in the body of the request
+    Query(options): Query<ImportOptions>,
+    // we expect the file to be sent as is in the body of the request
     body: Bytes,
 ) -> Result<Json<Value>, (StatusCode, String)> {
-    shared_logic::db::import_eeg_data_from_csv(&app_state.db_client, session_id, &body)
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to import EEG data: {}", e),
-            )
-        })?;
+    let format = options.format.unwrap_or_else(|| "csv".to_string());
+    let imported = match format.to_lowercase().as_str() {
+        "csv" => {
+            shared_logic::db::import_eeg_data_from_csv(&app_state.db_client, session_id, &body)
+                .await
+        }
+        "xdf" => {
+            shared_logic::db::import_eeg_data_from_xdf(&app_state.db_client, session_id, &body)
+                .await
+        }
+        "edf" => {
+            shared_logic::db::import_eeg_data_from_edf(&app_state.db_client, session_id, &body)
+                .await
+        }
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Unsupported import format: {}", format),
+            ))
+        }
+    };
+    imported.map_err(|e| {
+        // the importers report files they can't parse as protocol errors
+        let status = match e {
+            sqlx::Error::Protocol(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, format!("Failed to import EEG data: {}", e))
+    })?;
 
     Ok(Json(json!({"status": "success"})))
 }
@@ -639,13 +801,23 @@ async fn main() {
         )
         .route("/api/sessions/:session_id/time-label", get(get_time_labels))
         .route("/api/sessions/:session_id/eeg-data", get(get_eeg_data))
+        .route("/api/sessions/:session_id/psd", get(get_psd))
+        .route(
+            "/api/sessions/:session_id/clock-offsets",
+            get(get_clock_offsets),
+        )
+        .route("/api/sessions/:session_id/gaps", get(get_stream_gaps))
+        .route(
+            "/api/sessions/:session_id/pipeline-history",
+            get(get_pipeline_history),
+        )
         .route(
             "/api/sessions/:session_id/eeg_data/export",
             post(export_eeg_data),
         )
         .route(
             "/api/sessions/:session_id/eeg_data/import",
-            post(import_eeg_data),
+            post(import_eeg_data).layer(DefaultBodyLimit::max(MAX_IMPORT_BYTES)),
         )
         // Share application state with all handlers
         .with_state(app_state);
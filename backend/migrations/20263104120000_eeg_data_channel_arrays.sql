@@ -0,0 +1,13 @@
+-- store EEG samples as one array per row instead of a fixed channel1..channel4,
+-- so headsets with any number of channels can be recorded (array index = channel index)
+ALTER TABLE eeg_data ADD COLUMN channels INTEGER[];
+
+UPDATE eeg_data SET channels = ARRAY[channel1, channel2, channel3, channel4];
+
+ALTER TABLE eeg_data ALTER COLUMN channels SET NOT NULL;
+
+ALTER TABLE eeg_data
+DROP COLUMN channel1,
+DROP COLUMN channel2,
+DROP COLUMN channel3,
+DROP COLUMN channel4;
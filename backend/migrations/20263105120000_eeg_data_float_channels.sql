@@ -0,0 +1,3 @@
+-- EEG samples arrive as f64 microvolts, storing them as integers truncated every reading
+ALTER TABLE eeg_data
+ALTER COLUMN channels TYPE DOUBLE PRECISION[] USING channels::DOUBLE PRECISION[];
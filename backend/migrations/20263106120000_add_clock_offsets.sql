@@ -0,0 +1,13 @@
+-- history of the LSL clock offset applied to eeg_data.time during a session
+
+CREATE TABLE IF NOT EXISTS clock_offsets (
+  id              SERIAL PRIMARY KEY,
+  session_id      INTEGER NOT NULL
+    REFERENCES sessions(id) ON DELETE CASCADE,
+  measured_at     TIMESTAMPTZ NOT NULL,
+  time_correction DOUBLE PRECISION NOT NULL,  -- remote LSL clock -> local LSL clock (seconds)
+  unix_offset     DOUBLE PRECISION NOT NULL,  -- local LSL clock -> Unix time (seconds)
+  applied_offset  DOUBLE PRECISION NOT NULL   -- smoothed total offset added to sample timestamps
+);
+
+CREATE INDEX IF NOT EXISTS clock_offsets_session_idx ON clock_offsets (session_id, measured_at);
@@ -0,0 +1,9 @@
+-- stretches of a session where the EEG stream was lost and had to be found again
+
+CREATE TABLE IF NOT EXISTS stream_gaps (
+  id         SERIAL PRIMARY KEY,
+  session_id INTEGER NOT NULL
+    REFERENCES sessions(id) ON DELETE CASCADE,
+  gap_start  TIMESTAMPTZ NOT NULL,   -- time of the last sample before the outage
+  gap_end    TIMESTAMPTZ NOT NULL    -- time of the first sample after it (or the session end)
+);
@@ -0,0 +1,9 @@
+-- pipelines a recording session ran with; each applies from applied_at until the next one
+
+CREATE TABLE IF NOT EXISTS pipeline_changes (
+  id         SERIAL PRIMARY KEY,
+  session_id INTEGER NOT NULL
+    REFERENCES sessions(id) ON DELETE CASCADE,
+  applied_at TIMESTAMPTZ NOT NULL,   -- time of the first sample processed with this pipeline
+  pipeline   JSONB NOT NULL          -- the pipeline as sent by the client (nodes array)
+);
@@ -1,17 +1,23 @@
 use tokio::sync::broadcast;
 use tokio::sync::broadcast::Receiver;
 
-use crate::db::{get_db_client, insert_batch_eeg};
-use crate::lsl::{receive_eeg, EEGDataPacket};
+use crate::db::{
+    get_db_client, insert_batch_eeg, insert_clock_offsets, insert_pipeline_changes,
+    insert_stream_gaps, insert_time_labels,
+};
+use crate::lsl::{receive_eeg, EEGDataPacket, HistoryEntry};
+use crate::markers::{receive_markers, MarkerConfig};
 use crate::mockeeg::generate_mock_data;
-use crate::pipeline::Pipeline;
+use crate::outlet::{outlet_receiver, OutletConfig};
+use crate::pipeline::{Pipeline, PreprocessingConfig};
+use crate::source::SourceConfig;
 use futures_util::stream::SplitSink;
 use futures_util::SinkExt;
 use log::{error, info};
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::net::TcpStream;
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc, watch, Mutex};
 use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
 use tokio_util::sync::CancellationToken;
 
@@ -19,27 +25,55 @@ use tokio_util::sync::CancellationToken;
 pub async fn start_broadcast(
     write: Arc<Mutex<SplitSink<WebSocketStream<TcpStream>, Message>>>,
     cancel_token: CancellationToken,
-    pipeline: Pipeline,
+    pipeline_rx: watch::Receiver<Pipeline>,
+    source: SourceConfig,
+    outlets: OutletConfig,
+    markers: Option<MarkerConfig>,
     session_id: i32,
 ) {
     let (tx, _rx) = broadcast::channel::<Arc<EEGDataPacket>>(1000); // size of the broadcast buffer, not recommand below 500, websocket will miss messages
     let rx_ws = tx.subscribe();
-    let rx_db = tx.subscribe();
-    let generator_token = cancel_token.clone();
+    // a replay plays back samples that are already stored, so nothing is written for it
+    let records = source.records();
+    let rx_db = records.then(|| tx.subscribe());
+    let rx_outlet = outlets.enabled().then(|| tx.subscribe());
+    // the database receiver stores blinks as labels when the current pipeline asks for it
+    let db_pipeline_rx = pipeline_rx.clone();
 
-    ////// spawn the mock data generator, comment out when connecting to the muse headset.
-    tokio::spawn(async move {
-        if let Err(e) = generate_mock_data(generator_token).await {
-            error!("Mock data generation failed: {}", e);
-        }
+    // a mock session records from a simulated headset the server publishes itself
+    if let SourceConfig::Mock(mock) = &source {
+        let mock = mock.clone();
+        let generator_token = cancel_token.clone();
+        let mock_channels = pipeline_rx
+            .borrow()
+            .preprocessing_config()
+            .map(|c| c.n_channels)
+            .unwrap_or_else(|| PreprocessingConfig::default().n_channels);
+        tokio::spawn(async move {
+            match generate_mock_data(generator_token, mock, mock_channels).await {
+                Ok(stats) => info!(
+                    "Mock generator achieved {:.2} Hz of a nominal {} Hz",
+                    stats.achieved_rate, stats.nominal_rate
+                ),
+                Err(e) => error!("Mock data generation failed: {}", e),
+            }
+        });
+    }
+
+    // clock offsets, gaps and pipeline changes are stored as they happen
+    let history_tx = records.then(|| {
+        let (history_tx, history_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            history_receiver(history_rx, session_id).await;
+        });
+        history_tx
     });
-    ////// comment out the code above when connecting to the muse headset.
 
     //spawn a sender task
     let tx_clone = tx.clone();
     let sender_token = cancel_token.clone();
     let sender = tokio::spawn(async move {
-        receive_eeg(tx_clone, sender_token, pipeline).await;
+        receive_eeg(tx_clone, sender_token, pipeline_rx, source, history_tx).await
     });
 
     // Subscribe for websocket Receiver
@@ -49,17 +83,85 @@ pub async fn start_broadcast(
     });
 
     // Subscribe for database Receiver
-    tokio::spawn(async move {
-        db_receiver(rx_db, session_id).await;
-    });
+    if let Some(rx_db) = rx_db {
+        tokio::spawn(async move {
+            db_receiver(rx_db, session_id, db_pipeline_rx).await;
+        });
+    }
+
+    // record the experiment's marker stream as time labels alongside the samples
+    if let Some(markers) = markers.filter(|_| records) {
+        let marker_token = cancel_token.clone();
+        tokio::spawn(async move {
+            if let Err(e) = receive_markers(marker_token, markers, session_id).await {
+                error!("Marker collection failed: {}", e);
+            }
+        });
+    }
+
+    // Subscribe for LSL outlet Receiver
+    if let Some(rx_outlet) = rx_outlet {
+        tokio::task::spawn_blocking(move || outlet_receiver(rx_outlet, outlets));
+    }
 
     //waits for sender to complete.
     match sender.await {
-        Ok(_) => info!("Task finished successfully"),
+        Ok(Ok(_)) if !records => {
+            info!("Replay finished");
+            send_message(&write, serde_json::json!({ "type": "replayFinished" })).await;
+        }
+        Ok(Ok(_)) => info!("Task finished successfully"),
+        Ok(Err(e)) => {
+            // the session never started (or died), stop the generator and tell the client why
+            cancel_token.cancel();
+            send_error(&write, &e).await;
+        }
         Err(e) => error!("Task panicked: {:?}", e),
     }
 }
 
+// history_receiver stores the session's history as the collection loop reports it: the clock
+// offsets (so its timestamps can be audited later), stream gaps and pipeline changes.
+// Runs until the collection loop drops its sender, however the session ends.
+async fn history_receiver(mut history_rx: mpsc::UnboundedReceiver<HistoryEntry>, session_id: i32) {
+    while let Some(entry) = history_rx.recv().await {
+        let client = get_db_client();
+        let stored = match &entry {
+            HistoryEntry::ClockOffsets(offsets) => {
+                insert_clock_offsets(&client, session_id, offsets).await
+            }
+            HistoryEntry::Gap(gap) => {
+                insert_stream_gaps(&client, session_id, std::slice::from_ref(gap)).await
+            }
+            HistoryEntry::PipelineChange(change) => {
+                insert_pipeline_changes(&client, session_id, std::slice::from_ref(change)).await
+            }
+        };
+        if let Err(e) = stored {
+            error!("Failed to store session history {:?}: {}", entry, e);
+        }
+    }
+}
+
+// send_error sends {"error": message} to the websocket client, so it can tell it apart from data packets.
+async fn send_error(
+    write: &Arc<Mutex<SplitSink<WebSocketStream<TcpStream>, Message>>>,
+    message: &str,
+) {
+    send_message(write, serde_json::json!({ "error": message })).await;
+}
+
+// send_message sends a JSON status message (anything that isn't a data packet) to the websocket client.
+async fn send_message(
+    write: &Arc<Mutex<SplitSink<WebSocketStream<TcpStream>, Message>>>,
+    message: serde_json::Value,
+) {
+    let mut write_guard = write.lock().await;
+    if let Err(e) = write_guard.send(Message::Text(message.to_string())).await {
+        error!("Failed to send message to client: {}", e);
+    }
+}
+
 // ws_broadcast_receiver takes a EEGDataPacket  struct from the broadcast sender, and converts it to JSON, then send it to the connected websocket client.
 pub async fn ws_receiver(
     write: &Arc<Mutex<SplitSink<WebSocketStream<TcpStream>, Message>>>,
@@ -78,7 +180,7 @@ pub async fn ws_receiver(
                 match serde_json::to_string(&eeg_packet) {
                     Ok(msg) => {
                         // info!("websocket got: {}", msg);  // debug purposes
-                        let num_samples = eeg_packet.signals[0].len();
+                        let num_samples = eeg_packet.timestamps.len();
                         info!("websocket got packet with {} samples", num_samples);
                         packet_count += 1; // for debug purposes
                         sample_count += num_samples;
@@ -112,7 +214,12 @@ pub async fn ws_receiver(
 
 //db_broadcast_receiver takes EEGDataPacket  struct from the broadcast sender and inserts it into the database
 // it inserts as a batch of 100.
-pub async fn db_receiver(mut rx_db: Receiver<Arc<EEGDataPacket>>, session_id: i32) {
+// Detected blinks are stored as time labels if the pipeline's blink detection node has store_labels set.
+pub async fn db_receiver(
+    mut rx_db: Receiver<Arc<EEGDataPacket>>,
+    session_id: i32,
+    pipeline_rx: watch::Receiver<Pipeline>,
+) {
     let db_client = get_db_client();
 
     let mut packet_count = 0; // for debug purposes
@@ -122,12 +229,23 @@ pub async fn db_receiver(mut rx_db: Receiver<Arc<EEGDataPacket>>, session_id: i3
     loop {
         match rx_db.recv().await {
             Ok(eeg_packet) => {
-                let num_samples = eeg_packet.signals[0].len();
-                info!("Database got packet with {} samples", num_samples); // debug purposes
+                let num_samples = eeg_packet
+                    .timestamps
+                    .len()
+                    .saturating_sub(eeg_packet.overlap_len);
+                info!("Database got packet with {} new samples", num_samples); // debug purposes
                 packet_count += 1; // for debug purposes
                 sample_count += num_samples; // for debug purposes
 
                 let db_client_clone = db_client.clone();
+                let blink_labels: Vec<_> = match pipeline_rx.borrow().blink_config() {
+                    Some(config) if config.store_labels => eeg_packet
+                        .blinks
+                        .iter()
+                        .map(|b| config.to_label(b))
+                        .collect(),
+                    _ => Vec::new(),
+                };
 
                 // Insert the packet directly
                 tokio::spawn(async move {
@@ -138,6 +256,13 @@ pub async fn db_receiver(mut rx_db: Receiver<Arc<EEGDataPacket>>, session_id: i3
                         error!("Packet insert failed: {:?}", e);
                     }
                     info!("Packet insert took {:?}", now.elapsed()); // for debug purposes
+                    if !blink_labels.is_empty() {
+                        if let Err(e) =
+                            insert_time_labels(&db_client_clone, session_id, blink_labels).await
+                        {
+                            error!("Blink label insert failed: {:?}", e);
+                        }
+                    }
                 });
             }
             Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
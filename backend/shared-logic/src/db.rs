@@ -1,6 +1,9 @@
 use super::models::{
-    EegDataRow, FrontendState, NewTimeLabel, NewUser, Session, TimeLabel, TimeSeriesData,
-    UpdateUser, User,
+    ClockOffset, EegDataRow, FrontendState, NewTimeLabel, NewUser, PipelineChange, Session,
+    StreamGap, TimeLabel, TimeSeriesData, UpdateUser, User,
+};
+use crate::formats::{
+    brainvision, edf, estimate_sample_rate, xdf, ImportedRecording, MAX_IMPORT_CHANNELS,
 };
 use crate::lsl::EEGDataPacket;
 use argon2::password_hash::SaltString;
@@ -19,16 +22,6 @@ pub static DB_POOL: OnceCell<Arc<PgPool>> = OnceCell::new();
 
 pub type DbClient = Arc<PgPool>;
 
-// struct for EEG rows to convert to CSV
-#[derive(serde::Serialize)]
-struct EEGCsvRow {
-    time: String,
-    channel1: i32,
-    channel2: i32,
-    channel3: i32,
-    channel4: i32,
-}
-
 pub async fn initialize_connection() -> Result<DbClient, Error> {
     dotenv().ok();
     let database_url = std::env::var("DATABASE_URL")
@@ -150,6 +143,8 @@ pub async fn get_testtime_series_data(client: &DbClient) -> Result<Vec<TimeSerie
 }
 
 /// Insert a batch of records into eeg_data.
+///
+/// Samples in the packet's overlap (repeated from the previous window) are skipped.
 pub async fn insert_batch_eeg(
     client: &DbClient,
     session_id: i32,
@@ -163,31 +158,44 @@ pub async fn insert_batch_eeg(
         return Ok(());
     }
 
-    // Construct a single SQL insert statement
-    let mut query_builder = sqlx::QueryBuilder::new(
-        "INSERT INTO eeg_data (session_id, time, channel1, channel2, channel3, channel4) ",
-    );
+    // Every channel needs a value for every timestamp
+    if let Some(ch_idx) = packet.signals.iter().position(|ch| ch.len() != n_samples) {
+        return Err(Error::Protocol(format!(
+            "Channel {} has {} samples, expected {}",
+            ch_idx + 1,
+            packet.signals[ch_idx].len(),
+            n_samples
+        )));
+    }
+
+    // Samples repeated from the previous window were already stored with that window
+    let first_new = packet.overlap_len.min(n_samples);
+    if first_new == n_samples {
+        info!("Skipping insert - packet has no new samples");
+        return Ok(());
+    }
 
-    // Iterate through all data in the packet, pairing timestamp to the signal, and insert them
+    // Construct a single SQL insert statement
+    let mut query_builder =
+        sqlx::QueryBuilder::new("INSERT INTO eeg_data (session_id, time, channels) ");
 
+    // Iterate through all data in the packet, pairing timestamp to the signal of every channel, and insert them
     query_builder.push_values(
-        (0..n_samples).map(|sample_idx| {
+        (first_new..n_samples).map(|sample_idx| {
             (
                 session_id,
                 &packet.timestamps[sample_idx],
-                packet.signals[0][sample_idx], // Channel 0
-                packet.signals[1][sample_idx], // Channel 1
-                packet.signals[2][sample_idx], // Channel 2
-                packet.signals[3][sample_idx], // Channel 3
+                packet
+                    .signals
+                    .iter()
+                    .map(|ch| ch[sample_idx])
+                    .collect::<Vec<f64>>(),
             )
         }),
-        |mut b, (session_id, timestamp, ch0, ch1, ch2, ch3)| {
+        |mut b, (session_id, timestamp, channels)| {
             b.push_bind(session_id)
                 .push_bind(timestamp)
-                .push_bind(ch0)
-                .push_bind(ch1)
-                .push_bind(ch2)
-                .push_bind(ch3);
+                .push_bind(channels);
         },
     );
 
@@ -195,7 +203,7 @@ pub async fn insert_batch_eeg(
     query_builder.build().execute(&**client).await?;
     info!(
         "EEG packet inserted successfully - {} data",
-        packet.timestamps.len()
+        n_samples - first_new
     );
     Ok(())
 }
@@ -470,6 +478,146 @@ pub async fn insert_time_labels(
     Ok(())
 }
 
+/// Insert the clock offset history of a recording session.
+///
+/// Rows are inserted in batches to stay under Postgres' bind parameter limit on long sessions.
+pub async fn insert_clock_offsets(
+    client: &DbClient,
+    session_id: i32,
+    offsets: &[ClockOffset],
+) -> Result<(), sqlx::Error> {
+    if offsets.is_empty() {
+        info!("Skipping insert - no clock offsets to insert");
+        return Ok(());
+    }
+
+    for batch in offsets.chunks(1000) {
+        let mut query_builder = sqlx::QueryBuilder::new(
+            "INSERT INTO clock_offsets (session_id, measured_at, time_correction, unix_offset, applied_offset) ",
+        );
+
+        query_builder.push_values(batch, |mut b, offset| {
+            b.push_bind(session_id)
+                .push_bind(offset.measured_at)
+                .push_bind(offset.time_correction)
+                .push_bind(offset.unix_offset)
+                .push_bind(offset.applied_offset);
+        });
+
+        query_builder.build().execute(&**client).await?;
+    }
+    info!(
+        "Inserted {} clock offsets for session {}",
+        offsets.len(),
+        session_id
+    );
+    Ok(())
+}
+
+/// Get the clock offset history for a given session, ordered by measurement time.
+pub async fn get_clock_offsets(
+    client: &DbClient,
+    session_id: i32,
+) -> Result<Vec<ClockOffset>, Error> {
+    let offsets = sqlx::query_as!(
+        ClockOffset,
+        "SELECT measured_at, time_correction, unix_offset, applied_offset FROM clock_offsets WHERE session_id = $1 ORDER BY measured_at",
+        session_id
+    )
+    .fetch_all(&**client)
+    .await?;
+
+    info!("Retrieved {} clock offsets.", offsets.len());
+    Ok(offsets)
+}
+
+/// Insert the stream gaps (outages) of a recording session.
+pub async fn insert_stream_gaps(
+    client: &DbClient,
+    session_id: i32,
+    gaps: &[StreamGap],
+) -> Result<(), sqlx::Error> {
+    if gaps.is_empty() {
+        return Ok(());
+    }
+
+    let mut query_builder =
+        sqlx::QueryBuilder::new("INSERT INTO stream_gaps (session_id, gap_start, gap_end) ");
+
+    query_builder.push_values(gaps, |mut b, gap| {
+        b.push_bind(session_id)
+            .push_bind(gap.gap_start)
+            .push_bind(gap.gap_end);
+    });
+
+    query_builder.build().execute(&**client).await?;
+    info!(
+        "Inserted {} stream gaps for session {}",
+        gaps.len(),
+        session_id
+    );
+    Ok(())
+}
+
+/// Get the stream gaps for a given session, ordered by start time.
+pub async fn get_stream_gaps(client: &DbClient, session_id: i32) -> Result<Vec<StreamGap>, Error> {
+    let gaps = sqlx::query_as!(
+        StreamGap,
+        "SELECT gap_start, gap_end FROM stream_gaps WHERE session_id = $1 ORDER BY gap_start",
+        session_id
+    )
+    .fetch_all(&**client)
+    .await?;
+
+    info!("Retrieved {} stream gaps.", gaps.len());
+    Ok(gaps)
+}
+
+/// Insert the pipelines a recording session ran with.
+pub async fn insert_pipeline_changes(
+    client: &DbClient,
+    session_id: i32,
+    changes: &[PipelineChange],
+) -> Result<(), sqlx::Error> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    let mut query_builder =
+        sqlx::QueryBuilder::new("INSERT INTO pipeline_changes (session_id, applied_at, pipeline) ");
+
+    query_builder.push_values(changes, |mut b, change| {
+        b.push_bind(session_id)
+            .push_bind(change.applied_at)
+            .push_bind(&change.pipeline);
+    });
+
+    query_builder.build().execute(&**client).await?;
+    info!(
+        "Inserted {} pipeline changes for session {}",
+        changes.len(),
+        session_id
+    );
+    Ok(())
+}
+
+/// Get the pipeline history for a given session, ordered by the time each change applied.
+pub async fn get_pipeline_changes(
+    client: &DbClient,
+    session_id: i32,
+) -> Result<Vec<PipelineChange>, Error> {
+    let changes = sqlx::query_as!(
+        PipelineChange,
+        "SELECT applied_at, pipeline FROM pipeline_changes WHERE session_id = $1 ORDER BY applied_at",
+        session_id
+    )
+    .fetch_all(&**client)
+    .await?;
+
+    info!("Retrieved {} pipeline changes.", changes.len());
+    Ok(changes)
+}
+
 /// Get EEG data rows for a given session within a time range.
 ///
 /// Returns all rows from eeg_data where session_id matches and time is between
@@ -487,7 +635,7 @@ pub async fn get_eeg_data_by_range(
 
     let data = sqlx::query_as!(
         EegDataRow,
-        "SELECT time, channel1, channel2, channel3, channel4 FROM eeg_data WHERE session_id = $1 AND time >= $2 AND time <= $3 ORDER BY time",
+        "SELECT time, channels FROM eeg_data WHERE session_id = $1 AND time >= $2 AND time <= $3 ORDER BY time",
         session_id,
         start,
         end,
@@ -544,40 +692,35 @@ pub async fn export_eeg_data_as_csv(
     );
 
     // get the data from the database
-    let data = sqlx::query!(
-        "SELECT time, channel1, channel2, channel3, channel4 FROM eeg_data
-        WHERE session_id = $1 AND time >= $2 AND time <= $3
-        ORDER BY time ASC",
-        session_id,
-        start_time,
-        end_time
-    )
-    .fetch_all(&**client)
-    .await?;
+    let data = get_eeg_data_by_range(client, session_id, start_time, end_time).await?;
 
-    // build the CSV using the csv crate
+    eeg_rows_to_csv(&data, include_header)
+}
 
+// Builds the CSV export from EEG rows. Channel values are written with f64's shortest
+// round-trip formatting, so parsing them back gives the exact stored value.
+fn eeg_rows_to_csv(data: &[EegDataRow], include_header: bool) -> Result<String, Error> {
+    // build the CSV using the csv crate
     let mut writer = csv::WriterBuilder::new()
         .has_headers(false)
         .from_writer(vec![]);
 
-    // write the header based on include_header flag
+    // write the header based on include_header flag, one column per channel of the session
     if include_header {
+        let n_channels = data.iter().map(|row| row.channels.len()).max().unwrap_or(0);
+        let header = std::iter::once("time".to_string())
+            .chain((1..=n_channels).map(|ch| format!("channel{}", ch)));
         writer
-            .write_record(["time", "channel1", "channel2", "channel3", "channel4"])
+            .write_record(header)
             .map_err(|e| Error::Protocol(e.to_string()))?;
     }
 
     // now, iterate through the data and write each row
     for row in data {
+        let record = std::iter::once(row.time.to_rfc3339())
+            .chain(row.channels.iter().map(|value| value.to_string()));
         writer
-            .serialize(EEGCsvRow {
-                time: row.time.to_rfc3339(),
-                channel1: row.channel1,
-                channel2: row.channel2,
-                channel3: row.channel3,
-                channel4: row.channel4,
-            })
+            .write_record(record)
             .map_err(|e| Error::Protocol(e.to_string()))?;
     }
 
@@ -592,7 +735,7 @@ pub async fn export_eeg_data_as_csv(
 }
 
 /// Import EEG data from a CSV byte stream for a given session ID. The CSV is expected
-/// to have columns: "time", "channel1", "channel2", "channel3", "channel4".
+/// to have a "time" column followed by one column per channel ("channel1", "channel2", ...).
 ///
 /// Returns Ok(()) on success.
 pub async fn import_eeg_data_from_csv(
@@ -602,24 +745,47 @@ pub async fn import_eeg_data_from_csv(
 ) -> Result<(), Error> {
     info!("Importing EEG data for session id {} from CSV", session_id);
 
+    let eeg_rows = parse_eeg_csv(csv_bytes)?;
+
+    // a long recording is more rows than one insert can bind
+    insert_eeg_in_batches(client, session_id, &eeg_rows).await
+}
+
+// Parses a CSV export back into an EEGDataPacket, one signal vector per channel column.
+fn parse_eeg_csv(csv_bytes: &[u8]) -> Result<EEGDataPacket, Error> {
     // we use the csv crate to read the CSV data, converting them to the struct we made for CSV rows
     let mut reader = csv::ReaderBuilder::new()
         .has_headers(true) // we expect the CSV to have headers, should probably make this clear somewhere
         .from_reader(csv_bytes);
 
+    // the number of channels is whatever the header has after the time column
+    let n_channels = reader
+        .headers()
+        .map_err(|e| Error::Protocol(e.to_string()))?
+        .len()
+        .saturating_sub(1);
+    if n_channels == 0 {
+        return Err(Error::Protocol(
+            "CSV must have a time column and at least one channel column".to_string(),
+        ));
+    }
+    if n_channels > MAX_IMPORT_CHANNELS {
+        return Err(Error::Protocol(format!(
+            "CSV has {} channel columns, at most {} are supported",
+            n_channels, MAX_IMPORT_CHANNELS
+        )));
+    }
+
     // set up our vectors to hold the parsed EEG data rows, so we can batch insert them later
     let mut timestamps: Vec<DateTime<Utc>> = Vec::new();
-    let mut channel1_data: Vec<f64> = Vec::new();
-    let mut channel2_data: Vec<f64> = Vec::new();
-    let mut channel3_data: Vec<f64> = Vec::new();
-    let mut channel4_data: Vec<f64> = Vec::new();
+    let mut signals: Vec<Vec<f64>> = vec![Vec::new(); n_channels];
 
     // we iterate through the CSV records, parsing each row and converting it to the format we need for insertion
     for result in reader.records() {
         // unwrap the record, if there's an error we return it
         let record = result.map_err(|e| Error::Protocol(e.to_string()))?;
 
-        // now we parse the fields, converting time to DateTime<Utc> and channels to i32
+        // now we parse the fields, converting time to DateTime<Utc> and channels to f64
         let time_str = record
             .get(0)
             .ok_or_else(|| Error::Protocol("Missing time field".to_string()))?;
@@ -629,44 +795,170 @@ pub async fn import_eeg_data_from_csv(
             .map_err(|e| Error::Protocol(format!("Invalid time format: {}", e)))?
             .with_timezone(&Utc);
 
-        let channel1 = record
-            .get(1)
-            .ok_or_else(|| Error::Protocol("Missing channel1 field".to_string()))?
-            .parse::<f64>()
-            .map_err(|e| Error::Protocol(format!("Invalid channel1 value: {}", e)))?;
-        let channel2 = record
-            .get(2)
-            .ok_or_else(|| Error::Protocol("Missing channel2 field".to_string()))?
-            .parse::<f64>()
-            .map_err(|e| Error::Protocol(format!("Invalid channel2 value: {}", e)))?;
-        let channel3 = record
-            .get(3)
-            .ok_or_else(|| Error::Protocol("Missing channel3 field".to_string()))?
-            .parse::<f64>()
-            .map_err(|e| Error::Protocol(format!("Invalid channel3 value: {}", e)))?;
-        let channel4 = record
-            .get(4)
-            .ok_or_else(|| Error::Protocol("Missing channel4 field".to_string()))?
-            .parse::<f64>()
-            .map_err(|e| Error::Protocol(format!("Invalid channel4 value: {}", e)))?;
-
-        // now we construct the tuple and add it to our vectors
+        // now we parse each channel value and add it to our vectors
+        for (ch_idx, channel) in signals.iter_mut().enumerate() {
+            let value = record
+                .get(ch_idx + 1)
+                .ok_or_else(|| Error::Protocol(format!("Missing channel{} field", ch_idx + 1)))?
+                .parse::<f64>()
+                .map_err(|e| {
+                    Error::Protocol(format!("Invalid channel{} value: {}", ch_idx + 1, e))
+                })?;
+            channel.push(value);
+        }
         timestamps.push(time);
-        channel1_data.push(channel1);
-        channel2_data.push(channel2);
-        channel3_data.push(channel3);
-        channel4_data.push(channel4);
     }
 
-    // now we use our existing batch insert function to insert the data into the database
-    let eeg_rows = EEGDataPacket {
+    Ok(EEGDataPacket {
         timestamps,
-        signals: vec![channel1_data, channel2_data, channel3_data, channel4_data],
-        ml_result: None,
-    };
+        signals,
+        ..Default::default()
+    })
+}
 
-    insert_batch_eeg(client, session_id, &eeg_rows).await?;
+/// Export the EEG data and time labels for a given session ID and time range as an XDF file.
+///
+/// Returns the file contents on success.
+pub async fn export_eeg_data_as_xdf(
+    client: &DbClient,
+    session_id: i32,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+) -> Result<Vec<u8>, Error> {
+    info!(
+        "Exporting EEG data for session id {} from {} to {} as XDF",
+        session_id, start_time, end_time
+    );
+
+    let data = get_eeg_data_by_range(client, session_id, start_time, end_time).await?;
+    let labels = get_time_labels_by_range(client, session_id, start_time, end_time).await?;
+    let times: Vec<DateTime<Utc>> = data.iter().map(|row| row.time).collect();
+    let sample_rate = estimate_sample_rate(&times).unwrap_or(0.0);
+
+    Ok(xdf::write_xdf(&data, &labels, sample_rate))
+}
+
+/// Import an XDF file (e.g. from LabRecorder) into a given session ID. The EEG stream's
+/// samples go into eeg_data and every marker becomes a time label.
+///
+/// Returns Ok(()) on success.
+pub async fn import_eeg_data_from_xdf(
+    client: &DbClient,
+    session_id: i32,
+    xdf_bytes: &[u8],
+) -> Result<(), Error> {
+    info!("Importing EEG data for session id {} from XDF", session_id);
+
+    let recording = xdf::read_xdf(xdf_bytes).map_err(Error::Protocol)?;
+    insert_imported_recording(client, session_id, recording).await
+}
+
+/// Export the EEG data and time labels for a given session ID and time range as an EDF+ file,
+/// with the labels as annotations.
+///
+/// Returns the file contents on success.
+pub async fn export_eeg_data_as_edf(
+    client: &DbClient,
+    session_id: i32,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+) -> Result<Vec<u8>, Error> {
+    info!(
+        "Exporting EEG data for session id {} from {} to {} as EDF+",
+        session_id, start_time, end_time
+    );
+
+    let data = get_eeg_data_by_range(client, session_id, start_time, end_time).await?;
+    let labels = get_time_labels_by_range(client, session_id, start_time, end_time).await?;
+    let times: Vec<DateTime<Utc>> = data.iter().map(|row| row.time).collect();
+    let sample_rate = estimate_sample_rate(&times)
+        .ok_or_else(|| Error::Protocol("Need at least 2 samples to export EDF".to_string()))?;
+
+    edf::write_edf(&data, &labels, sample_rate).map_err(Error::Protocol)
+}
+
+/// Export EEG data for a given session ID and time range as a BrainVision recording
+/// (.vhdr/.vmrk/.eeg named after the session, zipped together). Time labels in the range
+/// become markers in the .vmrk file.
+///
+/// Returns the zip archive on success.
+pub async fn export_eeg_data_as_brainvision(
+    client: &DbClient,
+    session_id: i32,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+) -> Result<Vec<u8>, Error> {
+    info!(
+        "Exporting EEG data for session id {} from {} to {} as BrainVision",
+        session_id, start_time, end_time
+    );
 
+    let data = get_eeg_data_by_range(client, session_id, start_time, end_time).await?;
+    let labels = get_time_labels_by_range(client, session_id, start_time, end_time).await?;
+    let times: Vec<DateTime<Utc>> = data.iter().map(|row| row.time).collect();
+    let sample_rate = estimate_sample_rate(&times).ok_or_else(|| {
+        Error::Protocol("Need at least 2 samples to export BrainVision".to_string())
+    })?;
+
+    let name = format!("session_{}", session_id);
+    brainvision::write_brainvision(&data, &labels, sample_rate, &name).map_err(Error::Protocol)
+}
+
+/// Import an EDF or EDF+ file into a given session ID. Its signals go into eeg_data and
+/// its annotations become time labels.
+///
+/// Returns Ok(()) on success.
+pub async fn import_eeg_data_from_edf(
+    client: &DbClient,
+    session_id: i32,
+    edf_bytes: &[u8],
+) -> Result<(), Error> {
+    info!("Importing EEG data for session id {} from EDF", session_id);
+
+    let recording = edf::read_edf(edf_bytes).map_err(Error::Protocol)?;
+    insert_imported_recording(client, session_id, recording).await
+}
+
+// Stores a recording read from a file.
+async fn insert_imported_recording(
+    client: &DbClient,
+    session_id: i32,
+    recording: ImportedRecording,
+) -> Result<(), Error> {
+    insert_eeg_in_batches(client, session_id, &recording.packet).await?;
+
+    info!(
+        "Imported {} samples and {} labels into session {}",
+        recording.packet.timestamps.len(),
+        recording.labels.len(),
+        session_id
+    );
+    insert_time_labels(client, session_id, recording.labels).await
+}
+
+// Inserts the samples of a whole recording in batches, to stay under Postgres' bind
+// parameter limit.
+async fn insert_eeg_in_batches(
+    client: &DbClient,
+    session_id: i32,
+    packet: &EEGDataPacket,
+) -> Result<(), Error> {
+    const BATCH_SAMPLES: usize = 10_000;
+
+    let n_samples = packet.timestamps.len();
+    for start in (0..n_samples).step_by(BATCH_SAMPLES) {
+        let end = (start + BATCH_SAMPLES).min(n_samples);
+        let batch = EEGDataPacket {
+            timestamps: packet.timestamps[start..end].to_vec(),
+            signals: packet
+                .signals
+                .iter()
+                .map(|ch| ch[start..end].to_vec())
+                .collect(),
+            ..Default::default()
+        };
+        insert_batch_eeg(client, session_id, &batch).await?;
+    }
     Ok(())
 }
 
@@ -686,3 +978,51 @@ pub async fn get_earliest_eeg_timestamp(
 
     Ok(row.earliest_time)
 }
+
+pub async fn get_latest_eeg_timestamp(
+    client: &DbClient,
+    session_id: i32,
+) -> Result<Option<DateTime<Utc>>, Error> {
+    let row = sqlx::query!(
+        "SELECT MAX(time) as latest_time FROM eeg_data WHERE session_id = $1",
+        session_id
+    )
+    .fetch_one(&**client)
+    .await?;
+
+    Ok(row.latest_time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_export_import_round_trips_exactly() {
+        let start = DateTime::parse_from_rfc3339("2025-01-01T12:00:00.123456Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let rows: Vec<EegDataRow> = (0..8)
+            .map(|i| EegDataRow {
+                time: start + chrono::Duration::microseconds(3906 * i),
+                channels: vec![
+                    -12.345678901234567 * i as f64,
+                    0.1 + 0.2,
+                    f64::MIN_POSITIVE,
+                    1e300 / (i + 1) as f64,
+                ],
+            })
+            .collect();
+
+        let csv_data = eeg_rows_to_csv(&rows, true).unwrap();
+        let packet = parse_eeg_csv(csv_data.as_bytes()).unwrap();
+
+        assert_eq!(packet.timestamps.len(), rows.len());
+        for (i, row) in rows.iter().enumerate() {
+            assert_eq!(packet.timestamps[i], row.time);
+            for (ch, value) in row.channels.iter().enumerate() {
+                assert_eq!(packet.signals[ch][i].to_bits(), value.to_bits());
+            }
+        }
+    }
+}
@@ -0,0 +1,212 @@
+// BrainVision Core Data Format, read by BrainVision Analyzer and MNE's read_raw_brainvision.
+// Spec: https://www.brainproducts.com/support-resources/brainvision-core-data-format-1-0/
+//
+// A recording is three files: the .vhdr header (INI-like text describing the channels and
+// pointing at the other two), the .vmrk marker file, and the .eeg binary data. We write the
+// data as multiplexed little-endian 32-bit floats. The three files are delivered as one zip.
+
+use std::io::{Cursor, Write};
+
+use chrono::{DateTime, Utc};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::models::{EegDataRow, TimeLabel};
+
+// Writes the rows and labels as a BrainVision recording named `name` (name.vhdr, name.vmrk,
+// name.eeg) and returns the three files zipped. Labels become "Comment" markers; gaps in
+// the session longer than a couple of samples start a new segment, like a paused recording.
+pub fn write_brainvision(
+    rows: &[EegDataRow],
+    labels: &[TimeLabel],
+    sample_rate: f64,
+    name: &str,
+) -> Result<Vec<u8>, String> {
+    if rows.is_empty() {
+        return Err("No EEG data to export".to_string());
+    }
+    if !(sample_rate.is_finite() && sample_rate > 0.0) {
+        return Err(format!("Invalid sample rate {}", sample_rate));
+    }
+    let n_channels = rows.iter().map(|row| row.channels.len()).max().unwrap_or(0);
+
+    let header = header_file(name, n_channels, sample_rate);
+    let markers = marker_file(name, rows, labels, sample_rate);
+    let data = data_file(rows, n_channels);
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (extension, contents) in [
+        ("vhdr", header.as_bytes()),
+        ("vmrk", markers.as_bytes()),
+        ("eeg", data.as_slice()),
+    ] {
+        zip.start_file(format!("{}.{}", name, extension), options)
+            .and_then(|_| zip.write_all(contents).map_err(Into::into))
+            .map_err(|e| format!("Failed to write {}.{}: {}", name, extension, e))?;
+    }
+    let cursor = zip
+        .finish()
+        .map_err(|e| format!("Failed to write BrainVision archive: {}", e))?;
+    Ok(cursor.into_inner())
+}
+
+fn header_file(name: &str, n_channels: usize, sample_rate: f64) -> String {
+    let mut vhdr = format!(
+        "Brain Vision Data Exchange Header File Version 1.0\r\n\
+         \r\n\
+         [Common Infos]\r\n\
+         Codepage=UTF-8\r\n\
+         DataFile={name}.eeg\r\n\
+         MarkerFile={name}.vmrk\r\n\
+         DataFormat=BINARY\r\n\
+         DataOrientation=MULTIPLEXED\r\n\
+         NumberOfChannels={n_channels}\r\n\
+         ; Sampling interval in microseconds\r\n\
+         SamplingInterval={interval}\r\n\
+         \r\n\
+         [Binary Infos]\r\n\
+         BinaryFormat=IEEE_FLOAT_32\r\n\
+         \r\n\
+         [Channel Infos]\r\n\
+         ; Ch<n>=<name>,<reference>,<resolution>,<unit>\r\n",
+        interval = 1_000_000.0 / sample_rate,
+    );
+    for ch in 1..=n_channels {
+        vhdr.push_str(&format!("Ch{ch}=Ch{ch},,1,µV\r\n"));
+    }
+    vhdr
+}
+
+fn marker_file(name: &str, rows: &[EegDataRow], labels: &[TimeLabel], sample_rate: f64) -> String {
+    let mut vmrk = format!(
+        "Brain Vision Data Exchange Marker File, Version 1.0\r\n\
+         \r\n\
+         [Common Infos]\r\n\
+         Codepage=UTF-8\r\n\
+         DataFile={name}.eeg\r\n\
+         \r\n\
+         [Marker Infos]\r\n\
+         ; Mk<n>=<type>,<description>,<position>,<points>,<channel>[,<date>]\r\n\
+         ; positions are 1-based sample numbers, channel 0 means all channels\r\n",
+    );
+    let mut markers = Vec::new();
+
+    // a new segment at the start and after every gap, carrying the time of its first sample
+    let max_interval = chrono::Duration::nanoseconds((1.5e9 / sample_rate) as i64);
+    for (i, row) in rows.iter().enumerate() {
+        if i == 0 || row.time - rows[i - 1].time > max_interval {
+            markers.push(format!(
+                "New Segment,,{},1,0,{}",
+                i + 1,
+                format_marker_date(row.time)
+            ));
+        }
+    }
+
+    // labels start at the first sample at or after them, spanning the samples they cover
+    for label in labels {
+        let position = rows.partition_point(|row| row.time < label.start_timestamp);
+        if position >= rows.len() {
+            continue;
+        }
+        let points = label
+            .end_timestamp
+            .map(|end| {
+                rows.partition_point(|row| row.time <= end)
+                    .saturating_sub(position)
+            })
+            .unwrap_or(1)
+            .max(1);
+        markers.push(format!(
+            "Comment,{},{},{},0",
+            escape_description(&label.label),
+            position + 1,
+            points
+        ));
+    }
+
+    for (i, marker) in markers.iter().enumerate() {
+        vmrk.push_str(&format!("Mk{}={}\r\n", i + 1, marker));
+    }
+    vmrk
+}
+
+// Samples one after another, each holding every channel (missing ones are written as 0)
+fn data_file(rows: &[EegDataRow], n_channels: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(rows.len() * n_channels * 4);
+    for row in rows {
+        for ch in 0..n_channels {
+            let value = row.channels.get(ch).copied().unwrap_or(0.0) as f32;
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    data
+}
+
+// YYYYMMDDhhmmssuuuuuu, as used by the New Segment marker
+fn format_marker_date(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%d%H%M%S%6f").to_string()
+}
+
+// Commas separate marker fields, the format escapes them in descriptions as "\1"
+fn escape_description(text: &str) -> String {
+    text.replace(['\r', '\n'], " ").replace(',', "\\1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_brainvision_archive_holds_data_and_markers() {
+        let start = DateTime::parse_from_rfc3339("2025-01-01T12:00:00.5Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        // 4 ms apart with a one second gap after the 10th sample
+        let rows: Vec<EegDataRow> = (0..20)
+            .map(|i| EegDataRow {
+                time: start
+                    + chrono::Duration::milliseconds(4 * i + if i >= 10 { 1000 } else { 0 }),
+                channels: vec![i as f64, -1.5],
+            })
+            .collect();
+        let labels = vec![TimeLabel {
+            id: 1,
+            session_id: 1,
+            start_timestamp: start + chrono::Duration::milliseconds(8),
+            end_timestamp: Some(start + chrono::Duration::milliseconds(20)),
+            label: "eyes closed, resting".to_string(),
+            color: "teal-300".to_string(),
+        }];
+
+        let bytes = write_brainvision(&rows, &labels, 250.0, "session_1").unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut read = |file: &str| {
+            let mut contents = Vec::new();
+            archive
+                .by_name(file)
+                .unwrap()
+                .read_to_end(&mut contents)
+                .unwrap();
+            contents
+        };
+
+        let vhdr = String::from_utf8(read("session_1.vhdr")).unwrap();
+        assert!(vhdr.contains("NumberOfChannels=2\r\n"));
+        assert!(vhdr.contains("SamplingInterval=4000\r\n"));
+        assert!(vhdr.contains("Ch2=Ch2,,1,µV\r\n"));
+
+        let vmrk = String::from_utf8(read("session_1.vmrk")).unwrap();
+        assert!(vmrk.contains("Mk1=New Segment,,1,1,0,20250101120000500000\r\n"));
+        assert!(vmrk.contains("Mk2=New Segment,,11,1,0,20250101120001540000\r\n"));
+        assert!(vmrk.contains("Mk3=Comment,eyes closed\\1 resting,3,4,0\r\n"));
+
+        let eeg = read("session_1.eeg");
+        assert_eq!(eeg.len(), 20 * 2 * 4);
+        let value = |i: usize| f32::from_le_bytes(eeg[i * 4..i * 4 + 4].try_into().unwrap());
+        assert_eq!(value(2 * 7), 7.0);
+        assert_eq!(value(2 * 7 + 1), -1.5);
+    }
+}
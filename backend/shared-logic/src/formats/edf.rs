@@ -0,0 +1,551 @@
+// EDF+ (European Data Format), read by EDFbrowser, MNE and EEGLAB.
+// Spec: https://www.edfplus.info/specs/edfplus.html
+//
+// A 256 byte header, then 256 bytes per signal, then data records: each record holds a fixed
+// number of 16-bit samples per signal, signal after signal. Labels are stored in the
+// "EDF Annotations" signal as TALs (time-stamped annotation lists).
+
+use chrono::{DateTime, NaiveDate, TimeZone, Timelike, Utc};
+
+use super::{
+    datetime_to_offset, offset_to_datetime, ImportedRecording, IMPORTED_LABEL_COLOR,
+    MAX_IMPORT_CHANNELS,
+};
+use crate::lsl::EEGDataPacket;
+use crate::models::{EegDataRow, NewTimeLabel, TimeLabel};
+
+const ANNOTATIONS_LABEL: &str = "EDF Annotations";
+const DIGITAL_MIN: i32 = -32768;
+const DIGITAL_MAX: i32 = 32767;
+// Extra subfield of the local recording identification with the number of samples per
+// signal, so the padding of the last record can be dropped on import.
+const SAMPLE_COUNT_PREFIX: &str = "samples:";
+
+// TAL separators
+const TAL_DURATION: char = '\u{15}';
+const TAL_SEPARATOR: char = '\u{14}';
+const TAL_END: char = '\0';
+
+// Writes the rows as an EDF+ file with one signal per channel (in microvolts) plus an
+// annotations signal holding the labels. Each data record holds one second of samples at
+// `sample_rate`; the last one is padded with the final sample and the real sample count is
+// noted in the header. Records carry the time of their first sample, so gaps in the session
+// are kept (the file is then EDF+D).
+pub fn write_edf(
+    rows: &[EegDataRow],
+    labels: &[TimeLabel],
+    sample_rate: f64,
+) -> Result<Vec<u8>, String> {
+    if rows.is_empty() {
+        return Err("No EEG data to export".to_string());
+    }
+    if !(sample_rate.is_finite() && sample_rate > 0.0) {
+        return Err(format!("Invalid sample rate {}", sample_rate));
+    }
+    let n_channels = rows.iter().map(|row| row.channels.len()).max().unwrap_or(0);
+    let samples_per_record = (sample_rate.round() as usize).max(1);
+    let record_duration = samples_per_record as f64 / sample_rate;
+    let n_records = rows.len().div_ceil(samples_per_record);
+
+    // EDF start times have whole seconds, the fraction goes into the first record's onset
+    let start = rows[0].time.with_nanosecond(0).unwrap_or(rows[0].time);
+    let onsets: Vec<f64> = (0..n_records)
+        .map(|r| datetime_to_offset(start, rows[r * samples_per_record].time))
+        .collect();
+    let continuous = onsets.iter().enumerate().all(|(r, onset)| {
+        (onset - onsets[0] - r as f64 * record_duration).abs() < 0.5 / sample_rate
+    });
+
+    // Every record starts with its time-keeping TAL, labels go in the record they start in
+    let mut annotations: Vec<String> = onsets
+        .iter()
+        .map(|&onset| {
+            format!(
+                "+{}{}{}{}",
+                format_secs(onset),
+                TAL_SEPARATOR,
+                TAL_SEPARATOR,
+                TAL_END
+            )
+        })
+        .collect();
+    for label in labels {
+        let onset = datetime_to_offset(start, label.start_timestamp);
+        let record = onsets.partition_point(|&o| o <= onset).saturating_sub(1);
+        let duration = label
+            .end_timestamp
+            .map(|end| {
+                format!(
+                    "{}{}",
+                    TAL_DURATION,
+                    format_secs(datetime_to_offset(label.start_timestamp, end))
+                )
+            })
+            .unwrap_or_default();
+        let text: String = label
+            .label
+            .chars()
+            .filter(|c| ![TAL_DURATION, TAL_SEPARATOR, TAL_END].contains(c))
+            .collect();
+        annotations[record].push_str(&format!(
+            "{}{}{}{}{}{}",
+            if onset < 0.0 { "" } else { "+" },
+            format_secs(onset),
+            duration,
+            TAL_SEPARATOR,
+            text,
+            TAL_SEPARATOR
+        ));
+        annotations[record].push(TAL_END);
+    }
+    let annotation_samples = annotations
+        .iter()
+        .map(|a| a.len())
+        .max()
+        .unwrap_or(0)
+        .div_ceil(2);
+
+    // Physical range of each channel, mapped onto the full 16-bit digital range
+    let ranges: Vec<(f64, f64)> = (0..n_channels)
+        .map(|ch| {
+            let (min, max) = rows
+                .iter()
+                .filter_map(|row| row.channels.get(ch))
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| {
+                    (lo.min(v), hi.max(v))
+                });
+            let (min, max) = (min.floor(), max.ceil());
+            if min < max {
+                (min, max)
+            } else {
+                (min - 1.0, min + 1.0)
+            }
+        })
+        .collect();
+
+    let n_signals = n_channels + 1;
+    let mut out = Vec::new();
+    push_field(&mut out, "0", 8);
+    push_field(&mut out, "X X X X", 80);
+    push_field(
+        &mut out,
+        &format!(
+            "Startdate {} X X MOSS {}{}",
+            start.format("%d-%b-%Y").to_string().to_uppercase(),
+            SAMPLE_COUNT_PREFIX,
+            rows.len()
+        ),
+        80,
+    );
+    push_field(&mut out, &start.format("%d.%m.%y").to_string(), 8);
+    push_field(&mut out, &start.format("%H.%M.%S").to_string(), 8);
+    push_field(&mut out, &(256 * (n_signals + 1)).to_string(), 8);
+    push_field(&mut out, if continuous { "EDF+C" } else { "EDF+D" }, 44);
+    push_field(&mut out, &n_records.to_string(), 8);
+    push_field(&mut out, &format_number(record_duration), 8);
+    push_field(&mut out, &n_signals.to_string(), 4);
+
+    // Signal headers are stored field by field: all labels, then all transducers, ...
+    let signal_headers: Vec<[(String, usize); 10]> = (0..n_signals)
+        .map(|s| {
+            let (unit, min, max, samples) = match ranges.get(s) {
+                Some(&(min, max)) => (
+                    "uV",
+                    format_number(min),
+                    format_number(max),
+                    samples_per_record,
+                ),
+                None => ("", "-1".to_string(), "1".to_string(), annotation_samples),
+            };
+            [
+                (signal_label(s, n_channels), 16),
+                (String::new(), 80), // transducer
+                (unit.to_string(), 8),
+                (min, 8),
+                (max, 8),
+                (DIGITAL_MIN.to_string(), 8),
+                (DIGITAL_MAX.to_string(), 8),
+                (String::new(), 80), // prefiltering
+                (samples.to_string(), 8),
+                (String::new(), 32), // reserved
+            ]
+        })
+        .collect();
+    for field in 0..10 {
+        for header in &signal_headers {
+            let (value, width) = &header[field];
+            push_field(&mut out, value, *width);
+        }
+    }
+
+    for (record, annotation) in annotations.iter().enumerate() {
+        let first = record * samples_per_record;
+        for (ch, &(min, max)) in ranges.iter().enumerate() {
+            for i in first..first + samples_per_record {
+                let row = &rows[i.min(rows.len() - 1)];
+                let value = row.channels.get(ch).copied().unwrap_or(0.0);
+                let digital = ((value - min) / (max - min) * (DIGITAL_MAX - DIGITAL_MIN) as f64
+                    + DIGITAL_MIN as f64)
+                    .round()
+                    .clamp(DIGITAL_MIN as f64, DIGITAL_MAX as f64)
+                    as i16;
+                out.extend(digital.to_le_bytes());
+            }
+        }
+        let mut bytes = annotation.as_bytes().to_vec();
+        bytes.resize(annotation_samples * 2, 0);
+        out.extend(bytes);
+    }
+
+    Ok(out)
+}
+
+fn signal_label(signal: usize, n_channels: usize) -> String {
+    if signal < n_channels {
+        format!("EEG channel{}", signal + 1)
+    } else {
+        ANNOTATIONS_LABEL.to_string()
+    }
+}
+
+// Header fields are ASCII, left aligned and padded with spaces.
+fn push_field(out: &mut Vec<u8>, value: &str, width: usize) {
+    let mut field: Vec<u8> = value.bytes().filter(|b| b.is_ascii()).take(width).collect();
+    field.resize(width, b' ');
+    out.extend(field);
+}
+
+// Formats a number in at most 8 characters, dropping decimals until it fits.
+fn format_number(value: f64) -> String {
+    (0..=6)
+        .rev()
+        .map(|decimals| trim_decimals(format!("{:.*}", decimals, value)))
+        .find(|s| s.len() <= 8)
+        .unwrap_or_else(|| format!("{:.0}", value))
+}
+
+// Seconds for TAL onsets and durations, to the microsecond the database stores.
+fn format_secs(secs: f64) -> String {
+    trim_decimals(format!("{:.6}", secs))
+}
+
+fn trim_decimals(s: String) -> String {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        s
+    }
+}
+
+// One signal of the file, from its header
+struct EdfSignal {
+    label: String,
+    physical_min: f64,
+    physical_max: f64,
+    digital_min: f64,
+    digital_max: f64,
+    samples_per_record: usize,
+}
+
+impl EdfSignal {
+    fn is_annotations(&self) -> bool {
+        self.label == ANNOTATIONS_LABEL
+    }
+
+    fn to_physical(&self, digital: i16) -> f64 {
+        let scale = (self.physical_max - self.physical_min) / (self.digital_max - self.digital_min);
+        (digital as f64 - self.digital_min) * scale + self.physical_min
+    }
+}
+
+// Reads an EDF or EDF+ file. Signals with the same number of samples per record as the first
+// one become the session's channels (others, recorded at a different rate, are skipped).
+// Annotations become labels. EDF has no time zone, the start time is taken as UTC. Files
+// written by write_edf are cut back to the sample count in their header.
+pub fn read_edf(bytes: &[u8]) -> Result<ImportedRecording, String> {
+    if bytes.len() < 256 {
+        return Err("EDF file is truncated".to_string());
+    }
+    let field = |start: usize, width: usize| -> Result<String, String> {
+        bytes
+            .get(start..start + width)
+            .map(|b| String::from_utf8_lossy(b).trim().to_string())
+            .ok_or_else(|| "EDF file is truncated".to_string())
+    };
+    let number = |start: usize, width: usize, name: &str| -> Result<f64, String> {
+        let value = field(start, width)?;
+        value
+            .parse::<f64>()
+            .map_err(|_| format!("Invalid {} \"{}\" in EDF header", name, value))
+    };
+    let count = |start: usize, width: usize, name: &str| -> Result<usize, String> {
+        let value = field(start, width)?;
+        value
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid {} \"{}\" in EDF header", name, value))
+    };
+
+    if field(0, 8)? != "0" {
+        return Err("Not an EDF file (version must be 0)".to_string());
+    }
+    let start = parse_edf_start(&field(168, 8)?, &field(176, 8)?)?;
+    // the subfields after Startdate, hospital code, investigator and equipment
+    let sample_count = field(88, 80)?
+        .split_whitespace()
+        .skip(5)
+        .find_map(|subfield| {
+            subfield
+                .strip_prefix(SAMPLE_COUNT_PREFIX)?
+                .parse::<usize>()
+                .ok()
+        });
+    let header_bytes = count(184, 8, "header size")?;
+    let record_duration = number(244, 8, "record duration")?;
+    let n_signals = count(252, 4, "signal count")?;
+    if n_signals == 0 || header_bytes != 256 * (n_signals + 1) {
+        return Err("EDF header size doesn't match its signal count".to_string());
+    }
+    // one signal may be the annotations
+    if n_signals > MAX_IMPORT_CHANNELS + 1 {
+        return Err(format!(
+            "EDF has {} signals, at most {} channels are supported",
+            n_signals, MAX_IMPORT_CHANNELS
+        ));
+    }
+
+    // Signal headers are stored field by field: all labels, then all transducers, ...
+    let offset = |field_start: usize, width: usize, signal: usize| {
+        256 + field_start * n_signals + signal * width
+    };
+    let signals = (0..n_signals)
+        .map(|s| {
+            Ok(EdfSignal {
+                label: field(offset(0, 16, s), 16)?,
+                physical_min: number(offset(104, 8, s), 8, "physical minimum")?,
+                physical_max: number(offset(112, 8, s), 8, "physical maximum")?,
+                digital_min: number(offset(120, 8, s), 8, "digital minimum")?,
+                digital_max: number(offset(128, 8, s), 8, "digital maximum")?,
+                samples_per_record: count(offset(216, 8, s), 8, "samples per record")?,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    if let Some(signal) = signals
+        .iter()
+        .find(|s| !s.is_annotations() && s.digital_min == s.digital_max)
+    {
+        return Err(format!(
+            "EDF signal \"{}\" has an empty digital range",
+            signal.label
+        ));
+    }
+
+    let record_bytes: usize = signals.iter().map(|s| s.samples_per_record * 2).sum();
+    if record_bytes == 0 {
+        return Err("EDF records are empty".to_string());
+    }
+    // the record count may be -1 (unknown) in the header, the file size is what counts
+    let n_records = bytes
+        .len()
+        .checked_sub(header_bytes)
+        .ok_or("EDF file is truncated")?
+        / record_bytes;
+
+    let data_rate = signals
+        .iter()
+        .find(|s| !s.is_annotations())
+        .map(|s| s.samples_per_record)
+        .ok_or("EDF file has no data signals")?;
+    let channels: Vec<usize> = (0..n_signals)
+        .filter(|&s| !signals[s].is_annotations() && signals[s].samples_per_record == data_rate)
+        .collect();
+
+    let mut timestamps = Vec::with_capacity(n_records * data_rate);
+    let mut signal_values = vec![Vec::with_capacity(n_records * data_rate); channels.len()];
+    let mut labels = Vec::new();
+    for record in 0..n_records {
+        let mut position = header_bytes + record * record_bytes;
+        let mut record_onset = record as f64 * record_duration;
+        let mut samples: Vec<&[u8]> = Vec::with_capacity(n_signals);
+        for signal in &signals {
+            let len = signal.samples_per_record * 2;
+            samples.push(&bytes[position..position + len]);
+            position += len;
+        }
+
+        for (signal, data) in signals.iter().zip(&samples) {
+            if !signal.is_annotations() {
+                continue;
+            }
+            for (i, (onset, duration, texts)) in parse_tals(data).into_iter().enumerate() {
+                // the first TAL of a record's first annotation signal keeps its time
+                if i == 0 && texts.iter().all(|t| t.is_empty()) {
+                    record_onset = onset;
+                    continue;
+                }
+                for text in texts.into_iter().filter(|t| !t.is_empty()) {
+                    labels.push(NewTimeLabel {
+                        start_timestamp: offset_to_datetime(start, onset),
+                        end_timestamp: duration.map(|d| offset_to_datetime(start, onset + d)),
+                        label: text,
+                        color: IMPORTED_LABEL_COLOR.to_string(),
+                    });
+                }
+            }
+        }
+
+        for i in 0..data_rate {
+            timestamps.push(offset_to_datetime(
+                start,
+                record_onset + i as f64 * record_duration / data_rate as f64,
+            ));
+        }
+        for (values, &s) in signal_values.iter_mut().zip(&channels) {
+            values.extend(
+                samples[s]
+                    .chunks_exact(2)
+                    .map(|b| signals[s].to_physical(i16::from_le_bytes([b[0], b[1]]))),
+            );
+        }
+    }
+
+    if let Some(n) = sample_count {
+        timestamps.truncate(n);
+        for values in &mut signal_values {
+            values.truncate(n);
+        }
+    }
+
+    Ok(ImportedRecording {
+        packet: EEGDataPacket {
+            timestamps,
+            signals: signal_values,
+            ..Default::default()
+        },
+        labels,
+    })
+}
+
+// Splits an annotation signal into (onset, duration, annotations) TALs.
+fn parse_tals(data: &[u8]) -> Vec<(f64, Option<f64>, Vec<String>)> {
+    String::from_utf8_lossy(data)
+        .split(TAL_END)
+        .filter(|tal| !tal.is_empty())
+        .filter_map(|tal| {
+            let mut parts = tal.split(TAL_SEPARATOR);
+            let mut timing = parts.next()?.split(TAL_DURATION);
+            let onset = timing.next()?.parse::<f64>().ok()?;
+            let duration = timing.next().and_then(|d| d.parse::<f64>().ok());
+            // the list ends with a separator, so the last part is always empty
+            let texts = parts
+                .map(|t| t.to_string())
+                .collect::<Vec<_>>()
+                .split_last()
+                .map(|(_, texts)| texts.to_vec())
+                .unwrap_or_default();
+            Some((onset, duration, texts))
+        })
+        .collect()
+}
+
+// Start date (dd.mm.yy, years 85-99 are 19xx) and time (hh.mm.ss) from the header.
+fn parse_edf_start(date: &str, time: &str) -> Result<DateTime<Utc>, String> {
+    let parse = |s: &str| -> Option<Vec<u32>> { s.split('.').map(|p| p.parse().ok()).collect() };
+    let invalid = || format!("Invalid EDF start \"{} {}\"", date, time);
+    let (d, t) = (
+        parse(date).ok_or_else(invalid)?,
+        parse(time).ok_or_else(invalid)?,
+    );
+    if d.len() != 3 || t.len() != 3 {
+        return Err(invalid());
+    }
+    let year = if d[2] >= 85 { 1900 + d[2] } else { 2000 + d[2] };
+    NaiveDate::from_ymd_opt(year as i32, d[1], d[0])
+        .and_then(|date| date.and_hms_opt(t[0], t[1], t[2]))
+        .map(|naive| Utc.from_utc_datetime(&naive))
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::estimate_sample_rate;
+
+    #[test]
+    fn test_read_edf_rejects_broken_headers() {
+        let rows: Vec<EegDataRow> = (0..10)
+            .map(|i| EegDataRow {
+                time: DateTime::from_timestamp(1_700_000_000 + i, 0).unwrap(),
+                channels: vec![i as f64],
+            })
+            .collect();
+        let bytes = write_edf(&rows, &[], 1.0).unwrap();
+        assert!(read_edf(&bytes).is_ok());
+
+        // cut inside the signal headers' reserved fields, before the size check could pass
+        assert!(read_edf(&bytes[..300]).is_err());
+        assert!(read_edf(&bytes[..720]).is_err());
+
+        // signal headers start at 256 + field offset * signal count (2 signals here)
+        let with_field = |start: usize, value: &str| {
+            let mut broken = bytes.clone();
+            let mut field = value.as_bytes().to_vec();
+            field.resize(8, b' ');
+            broken[start..start + 8].copy_from_slice(&field);
+            broken
+        };
+        // negative samples per record
+        assert!(read_edf(&with_field(256 + 216 * 2, "-1")).is_err());
+        // digital minimum equal to the maximum
+        assert!(read_edf(&with_field(256 + 120 * 2, "32767")).is_err());
+    }
+
+    #[test]
+    fn test_edf_round_trips_samples_and_annotations() {
+        let start = DateTime::parse_from_rfc3339("2025-01-01T12:00:00.123456Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let rows: Vec<EegDataRow> = (0..600)
+            .map(|i| EegDataRow {
+                time: start + chrono::Duration::microseconds(3906 * i),
+                channels: vec![(i as f64 * 0.1).sin() * 50.0, -12.5, i as f64],
+            })
+            .collect();
+        let labels = vec![TimeLabel {
+            id: 1,
+            session_id: 1,
+            start_timestamp: start + chrono::Duration::milliseconds(1500),
+            end_timestamp: Some(start + chrono::Duration::milliseconds(1750)),
+            label: "blink".to_string(),
+            color: "teal-300".to_string(),
+        }];
+
+        let times: Vec<_> = rows.iter().map(|row| row.time).collect();
+        let sample_rate = estimate_sample_rate(&times).unwrap();
+        let bytes = write_edf(&rows, &labels, sample_rate).unwrap();
+        let recording = read_edf(&bytes).unwrap();
+        let packet = &recording.packet;
+
+        // three one-second records, the padding of the last one is dropped
+        assert_eq!(packet.timestamps.len(), rows.len());
+        assert_eq!(packet.signals.len(), 3);
+        assert!(packet.signals.iter().all(|ch| ch.len() == rows.len()));
+        for (i, row) in rows.iter().enumerate() {
+            let error = (packet.timestamps[i] - row.time)
+                .num_microseconds()
+                .unwrap();
+            assert!(error.abs() <= 1, "sample {} is off by {}us", i, error);
+            for (ch, value) in row.channels.iter().enumerate() {
+                // 16-bit resolution over each channel's range
+                assert!((packet.signals[ch][i] - value).abs() < 0.01);
+            }
+        }
+
+        assert_eq!(recording.labels.len(), 1);
+        assert_eq!(recording.labels[0].label, "blink");
+        assert_eq!(
+            recording.labels[0].start_timestamp,
+            labels[0].start_timestamp
+        );
+        assert_eq!(recording.labels[0].end_timestamp, labels[0].end_timestamp);
+    }
+}
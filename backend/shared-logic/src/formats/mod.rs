@@ -0,0 +1,51 @@
+// File formats sessions are exported to and imported from, besides CSV (see db.rs).
+pub mod brainvision;
+pub mod edf;
+pub mod xdf;
+
+use chrono::{DateTime, Utc};
+
+use crate::lsl::EEGDataPacket;
+use crate::models::NewTimeLabel;
+
+// Color given to labels imported from files, which don't carry one
+pub const IMPORTED_LABEL_COLOR: &str = "teal-700";
+
+// Most channels an imported recording may have; a header claiming more is corrupt, and
+// trusting it would size buffers from untrusted input
+pub const MAX_IMPORT_CHANNELS: usize = 1024;
+
+// A recording read from a file, ready to be stored in a session.
+pub struct ImportedRecording {
+    pub packet: EEGDataPacket,
+    pub labels: Vec<NewTimeLabel>,
+}
+
+// Estimates the sample rate of a recording from the median interval between samples,
+// since sessions don't store the rate they were recorded at. None with fewer than 2 samples.
+pub fn estimate_sample_rate(times: &[DateTime<Utc>]) -> Option<f64> {
+    let mut intervals: Vec<i64> = times
+        .windows(2)
+        .filter_map(|w| (w[1] - w[0]).num_nanoseconds())
+        .filter(|&ns| ns > 0)
+        .collect();
+    if intervals.is_empty() {
+        return None;
+    }
+    let mid = intervals.len() / 2;
+    let (_, median, _) = intervals.select_nth_unstable(mid);
+    Some(1_000_000_000.0 / *median as f64)
+}
+
+// Converts seconds relative to `origin` to a DateTime.
+pub(crate) fn offset_to_datetime(origin: DateTime<Utc>, secs: f64) -> DateTime<Utc> {
+    origin + chrono::Duration::nanoseconds((secs * 1_000_000_000.0).round() as i64)
+}
+
+// Seconds from `origin` to `time`.
+pub(crate) fn datetime_to_offset(origin: DateTime<Utc>, time: DateTime<Utc>) -> f64 {
+    (time - origin)
+        .num_nanoseconds()
+        .map(|ns| ns as f64 / 1_000_000_000.0)
+        .unwrap_or_else(|| (time - origin).num_milliseconds() as f64 / 1000.0)
+}
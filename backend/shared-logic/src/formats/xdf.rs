@@ -0,0 +1,476 @@
+// XDF (Extensible Data Format), the format LabRecorder writes LSL recordings in.
+// Spec: https://github.com/sccn/xdf/wiki/Specifications
+//
+// A file is "XDF:" followed by chunks of [length][tag: u16][content]. Lengths and sample
+// counts are variable-length ints: one byte saying how many bytes follow (1, 4 or 8), then the value.
+// Timestamps are seconds on the recording machine's LSL clock, which only becomes wall-clock
+// time through the <datetime> in the file header.
+
+use chrono::{DateTime, Utc};
+
+use super::{
+    datetime_to_offset, offset_to_datetime, ImportedRecording, IMPORTED_LABEL_COLOR,
+    MAX_IMPORT_CHANNELS,
+};
+use crate::lsl::EEGDataPacket;
+use crate::models::{EegDataRow, NewTimeLabel, TimeLabel};
+
+const MAGIC: &[u8] = b"XDF:";
+
+const TAG_FILE_HEADER: u16 = 1;
+const TAG_STREAM_HEADER: u16 = 2;
+const TAG_SAMPLES: u16 = 3;
+const TAG_CLOCK_OFFSET: u16 = 4;
+
+const EEG_STREAM_ID: u32 = 1;
+const MARKER_STREAM_ID: u32 = 2;
+
+// Samples written per Samples chunk
+const SAMPLES_PER_CHUNK: usize = 1024;
+
+// Writes the EEG rows as a double64 EEG stream, and the labels as a string marker stream
+// (XDF markers are points in time, so each label becomes a marker at its start).
+// Timestamps are seconds from the first sample or label, whichever is earlier, which the
+// header's <datetime> is set to.
+pub fn write_xdf(rows: &[EegDataRow], labels: &[TimeLabel], sample_rate: f64) -> Vec<u8> {
+    let n_channels = rows.iter().map(|row| row.channels.len()).max().unwrap_or(0);
+    let origin = rows
+        .iter()
+        .map(|row| row.time)
+        .chain(labels.iter().map(|label| label.start_timestamp))
+        .min()
+        .unwrap_or_else(Utc::now);
+
+    let mut out = MAGIC.to_vec();
+    write_chunk(
+        &mut out,
+        TAG_FILE_HEADER,
+        format!(
+            "<?xml version=\"1.0\"?><info><version>1.0</version><datetime>{}</datetime></info>",
+            origin.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true)
+        )
+        .as_bytes(),
+    );
+
+    let channels: String = (1..=n_channels)
+        .map(|ch| {
+            format!(
+                "<channel><label>channel{}</label><unit>microvolts</unit><type>EEG</type></channel>",
+                ch
+            )
+        })
+        .collect();
+    write_stream_header(
+        &mut out,
+        EEG_STREAM_ID,
+        &format!(
+            "<name>MOSS EEG</name><type>EEG</type><channel_count>{}</channel_count>\
+             <nominal_srate>{}</nominal_srate><channel_format>double64</channel_format>\
+             <desc><channels>{}</channels></desc>",
+            n_channels, sample_rate, channels
+        ),
+    );
+    for chunk in rows.chunks(SAMPLES_PER_CHUNK) {
+        let mut content = EEG_STREAM_ID.to_le_bytes().to_vec();
+        write_varlen(&mut content, chunk.len() as u64);
+        for row in chunk {
+            content.push(8);
+            content.extend(datetime_to_offset(origin, row.time).to_le_bytes());
+            for ch in 0..n_channels {
+                let value = row.channels.get(ch).copied().unwrap_or(0.0);
+                content.extend(value.to_le_bytes());
+            }
+        }
+        write_chunk(&mut out, TAG_SAMPLES, &content);
+    }
+
+    if !labels.is_empty() {
+        write_stream_header(
+            &mut out,
+            MARKER_STREAM_ID,
+            "<name>MOSS Labels</name><type>Markers</type><channel_count>1</channel_count>\
+             <nominal_srate>0</nominal_srate><channel_format>string</channel_format>",
+        );
+        for chunk in labels.chunks(SAMPLES_PER_CHUNK) {
+            let mut content = MARKER_STREAM_ID.to_le_bytes().to_vec();
+            write_varlen(&mut content, chunk.len() as u64);
+            for label in chunk {
+                content.push(8);
+                content.extend(datetime_to_offset(origin, label.start_timestamp).to_le_bytes());
+                write_varlen(&mut content, label.label.len() as u64);
+                content.extend(label.label.as_bytes());
+            }
+            write_chunk(&mut out, TAG_SAMPLES, &content);
+        }
+    }
+
+    out
+}
+
+fn write_stream_header(out: &mut Vec<u8>, stream_id: u32, info: &str) {
+    let mut content = stream_id.to_le_bytes().to_vec();
+    content.extend(format!("<?xml version=\"1.0\"?><info>{}</info>", info).as_bytes());
+    write_chunk(out, TAG_STREAM_HEADER, &content);
+}
+
+fn write_chunk(out: &mut Vec<u8>, tag: u16, content: &[u8]) {
+    write_varlen(out, content.len() as u64 + 2);
+    out.extend(tag.to_le_bytes());
+    out.extend(content);
+}
+
+fn write_varlen(out: &mut Vec<u8>, value: u64) {
+    if value <= u8::MAX as u64 {
+        out.push(1);
+        out.push(value as u8);
+    } else if value <= u32::MAX as u64 {
+        out.push(4);
+        out.extend((value as u32).to_le_bytes());
+    } else {
+        out.push(8);
+        out.extend(value.to_le_bytes());
+    }
+}
+
+// One stream of the file, as far as it has been read
+struct XdfStream {
+    id: u32,
+    stream_type: String,
+    channel_count: usize,
+    format: String,
+    nominal_srate: f64,
+    timestamps: Vec<f64>,
+    // numeric streams, one value per channel per sample
+    values: Vec<Vec<f64>>,
+    // string streams, the first channel of each sample
+    markers: Vec<String>,
+    // (collection time, offset) pairs mapping the stream's clock to the recorder's
+    clock_offsets: Vec<(f64, f64)>,
+}
+
+impl XdfStream {
+    fn is_markers(&self) -> bool {
+        self.format == "string"
+    }
+
+    // The stream's timestamps on the recorder's clock, using the offset measured closest before each one.
+    fn corrected_timestamps(&self) -> Vec<f64> {
+        self.timestamps
+            .iter()
+            .map(|&t| {
+                // offsets are recorded in order; before the first one, the first is used
+                let i = self.clock_offsets.partition_point(|(at, _)| *at <= t);
+                let offset = self
+                    .clock_offsets
+                    .get(i.saturating_sub(1))
+                    .map_or(0.0, |(_, offset)| *offset);
+                t + offset
+            })
+            .collect()
+    }
+}
+
+// Reads an XDF file. The EEG stream is the first stream of type EEG (or the first numeric
+// stream if none says so), and every string stream is read as markers, each becoming a label.
+// Wall-clock times are the header's <datetime> plus the time since the earliest sample in the file.
+pub fn read_xdf(bytes: &[u8]) -> Result<ImportedRecording, String> {
+    let mut reader = Reader::new(bytes);
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err("Not an XDF file (missing XDF: magic)".to_string());
+    }
+
+    let mut datetime: Option<DateTime<Utc>> = None;
+    let mut streams: Vec<XdfStream> = Vec::new();
+    while !reader.is_empty() {
+        let length = reader.varlen()? as usize;
+        if length < 2 {
+            return Err(format!("Invalid XDF chunk length {}", length));
+        }
+        let mut chunk = Reader::new(reader.take(length)?);
+        let tag = u16::from_le_bytes(chunk.array()?);
+
+        match tag {
+            TAG_FILE_HEADER => {
+                let xml = chunk.rest_as_str()?;
+                datetime = xml_value(&xml, "datetime").and_then(|s| parse_xdf_datetime(&s));
+            }
+            TAG_STREAM_HEADER => {
+                let id = u32::from_le_bytes(chunk.array()?);
+                let xml = chunk.rest_as_str()?;
+                let channel_count = xml_value(&xml, "channel_count")
+                    .and_then(|s| s.parse::<usize>().ok())
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| format!("Stream {} has no channel_count", id))?;
+                if channel_count > MAX_IMPORT_CHANNELS {
+                    return Err(format!(
+                        "Stream {} has {} channels, at most {} are supported",
+                        id, channel_count, MAX_IMPORT_CHANNELS
+                    ));
+                }
+                streams.push(XdfStream {
+                    id,
+                    stream_type: xml_value(&xml, "type").unwrap_or_default(),
+                    channel_count,
+                    format: xml_value(&xml, "channel_format").unwrap_or_default(),
+                    nominal_srate: xml_value(&xml, "nominal_srate")
+                        .and_then(|s| s.parse().ok())
+                        .unwrap_or(0.0),
+                    timestamps: Vec::new(),
+                    values: Vec::new(),
+                    markers: Vec::new(),
+                    clock_offsets: Vec::new(),
+                });
+            }
+            TAG_SAMPLES => {
+                let id = u32::from_le_bytes(chunk.array()?);
+                let stream = streams
+                    .iter_mut()
+                    .find(|s| s.id == id)
+                    .ok_or_else(|| format!("Samples for stream {} before its header", id))?;
+                read_samples(&mut chunk, stream)?;
+            }
+            TAG_CLOCK_OFFSET => {
+                let id = u32::from_le_bytes(chunk.array()?);
+                let collected_at = f64::from_le_bytes(chunk.array()?);
+                let offset = f64::from_le_bytes(chunk.array()?);
+                if let Some(stream) = streams.iter_mut().find(|s| s.id == id) {
+                    stream.clock_offsets.push((collected_at, offset));
+                }
+            }
+            // boundary chunks, stream footers and unknown tags carry nothing we store
+            _ => {}
+        }
+    }
+
+    let datetime = datetime.ok_or("XDF file header has no <datetime>")?;
+    let eeg = streams
+        .iter()
+        .find(|s| s.stream_type.eq_ignore_ascii_case("EEG") && !s.is_markers())
+        .or_else(|| streams.iter().find(|s| !s.is_markers()))
+        .ok_or("XDF file has no numeric (EEG) stream")?;
+    let markers: Vec<&XdfStream> = streams.iter().filter(|s| s.is_markers()).collect();
+
+    // All streams are placed relative to the earliest sample in the file
+    let eeg_times = eeg.corrected_timestamps();
+    let marker_times: Vec<Vec<f64>> = markers.iter().map(|s| s.corrected_timestamps()).collect();
+    let first = eeg_times
+        .iter()
+        .chain(marker_times.iter().flatten())
+        .copied()
+        .fold(f64::INFINITY, f64::min);
+
+    let n_channels = eeg.values.iter().map(Vec::len).max().unwrap_or(0);
+    let mut signals: Vec<Vec<f64>> = (0..n_channels)
+        .map(|_| Vec::with_capacity(eeg.values.len()))
+        .collect();
+    for sample in &eeg.values {
+        for (channel, &value) in signals.iter_mut().zip(sample) {
+            channel.push(value);
+        }
+    }
+    let packet = EEGDataPacket {
+        timestamps: eeg_times
+            .iter()
+            .map(|&t| offset_to_datetime(datetime, t - first))
+            .collect(),
+        signals,
+        ..Default::default()
+    };
+
+    let labels = markers
+        .iter()
+        .zip(&marker_times)
+        .flat_map(|(stream, times)| stream.markers.iter().zip(times))
+        .filter(|(marker, _)| !marker.trim().is_empty())
+        .map(|(marker, &t)| NewTimeLabel {
+            start_timestamp: offset_to_datetime(datetime, t - first),
+            end_timestamp: None,
+            label: marker.clone(),
+            color: IMPORTED_LABEL_COLOR.to_string(),
+        })
+        .collect();
+
+    Ok(ImportedRecording { packet, labels })
+}
+
+fn read_samples(chunk: &mut Reader, stream: &mut XdfStream) -> Result<(), String> {
+    let n_samples = chunk.varlen()?;
+    for _ in 0..n_samples {
+        // samples without a timestamp follow the previous one at the nominal rate
+        let timestamp = match chunk.byte()? {
+            8 => f64::from_le_bytes(chunk.array()?),
+            0 => {
+                let previous = stream.timestamps.last().copied().unwrap_or(0.0);
+                if stream.nominal_srate > 0.0 {
+                    previous + 1.0 / stream.nominal_srate
+                } else {
+                    previous
+                }
+            }
+            n => return Err(format!("Invalid timestamp size {}", n)),
+        };
+        stream.timestamps.push(timestamp);
+
+        if stream.is_markers() {
+            let mut first = None;
+            for _ in 0..stream.channel_count {
+                let length = chunk.varlen()? as usize;
+                let value = String::from_utf8_lossy(chunk.take(length)?).into_owned();
+                first.get_or_insert(value);
+            }
+            stream.markers.push(first.unwrap_or_default());
+        } else {
+            let sample = (0..stream.channel_count)
+                .map(|_| read_value(chunk, &stream.format))
+                .collect::<Result<Vec<f64>, String>>()?;
+            stream.values.push(sample);
+        }
+    }
+    Ok(())
+}
+
+fn read_value(chunk: &mut Reader, format: &str) -> Result<f64, String> {
+    Ok(match format {
+        "float32" => f32::from_le_bytes(chunk.array()?) as f64,
+        "double64" => f64::from_le_bytes(chunk.array()?),
+        "int8" => i8::from_le_bytes(chunk.array()?) as f64,
+        "int16" => i16::from_le_bytes(chunk.array()?) as f64,
+        "int32" => i32::from_le_bytes(chunk.array()?) as f64,
+        "int64" => i64::from_le_bytes(chunk.array()?) as f64,
+        other => return Err(format!("Unsupported channel format \"{}\"", other)),
+    })
+}
+
+// Text between the first <tag> and its closing tag. The header XML is simple enough that
+// the first match is the <info>-level element for the tags read here.
+fn xml_value(xml: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&close)?;
+    Some(xml[start..end].trim().to_string())
+}
+
+// LabRecorder writes e.g. 2021-03-10T15:34:07+0100, which isn't quite RFC 3339.
+fn parse_xdf_datetime(s: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(s)
+        .or_else(|_| DateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%z"))
+        .or_else(|_| DateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f%z"))
+        .ok()
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+// Cursor over the file's bytes that fails instead of panicking on truncated input.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if n > self.bytes.len() {
+            return Err("XDF file is truncated".to_string());
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn varlen(&mut self) -> Result<u64, String> {
+        match self.byte()? {
+            1 => Ok(self.byte()? as u64),
+            4 => Ok(u32::from_le_bytes(self.array()?) as u64),
+            8 => Ok(u64::from_le_bytes(self.array()?)),
+            n => Err(format!("Invalid XDF length size {}", n)),
+        }
+    }
+
+    fn rest_as_str(&mut self) -> Result<String, String> {
+        let rest = self.take(self.bytes.len())?;
+        Ok(String::from_utf8_lossy(rest).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xdf_round_trips_samples_and_labels() {
+        let start = DateTime::parse_from_rfc3339("2025-01-01T12:00:00.123456Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let rows: Vec<EegDataRow> = (0..2000)
+            .map(|i| EegDataRow {
+                time: start + chrono::Duration::microseconds(3906 * i),
+                channels: vec![i as f64 * 0.1, -12.345678901234567, 1e-9, 0.1 + 0.2],
+            })
+            .collect();
+        let labels = vec![TimeLabel {
+            id: 1,
+            session_id: 1,
+            start_timestamp: start + chrono::Duration::milliseconds(500),
+            end_timestamp: None,
+            label: "eyes closed".to_string(),
+            color: "teal-300".to_string(),
+        }];
+
+        let bytes = write_xdf(&rows, &labels, 256.0);
+        let recording = read_xdf(&bytes).unwrap();
+
+        assert_eq!(recording.packet.timestamps.len(), rows.len());
+        assert_eq!(recording.packet.signals.len(), 4);
+        for (i, row) in rows.iter().enumerate() {
+            assert_eq!(recording.packet.timestamps[i], row.time);
+            for (ch, value) in row.channels.iter().enumerate() {
+                assert_eq!(recording.packet.signals[ch][i].to_bits(), value.to_bits());
+            }
+        }
+        assert_eq!(recording.labels.len(), 1);
+        assert_eq!(recording.labels[0].label, "eyes closed");
+        assert_eq!(
+            recording.labels[0].start_timestamp,
+            labels[0].start_timestamp
+        );
+
+        assert!(read_xdf(&bytes[..bytes.len() - 3]).is_err());
+        assert!(read_xdf(b"not xdf").is_err());
+    }
+
+    #[test]
+    fn test_xdf_rejects_oversized_channel_count() {
+        let start = Utc::now();
+        let rows = vec![EegDataRow {
+            time: start,
+            channels: vec![0.0; 1000],
+        }];
+        let bytes = write_xdf(&rows, &[], 256.0);
+        assert!(read_xdf(&bytes).is_ok());
+
+        // same length, so the chunk sizes still hold
+        let header = b"<channel_count>1000<";
+        let at = bytes
+            .windows(header.len())
+            .position(|w| w == header)
+            .unwrap();
+        let mut bytes = bytes;
+        bytes[at..at + header.len()].copy_from_slice(b"<channel_count>9999<");
+        let err = read_xdf(&bytes).err().unwrap();
+        assert!(err.contains("at most"), "{}", err);
+    }
+}
@@ -1,9 +1,14 @@
 // import as `shared_logic::db`, `share_logic::models`.
 pub mod bc;
 pub mod db;
+pub mod formats;
 pub mod lsl;
+pub mod markers;
 pub mod mockeeg;
 pub mod models;
+pub mod outlet;
 pub use models::{NewUser, TimeSeriesData, User};
 pub mod pipeline;
+pub mod replay;
 pub mod signal_processing;
+pub mod source;
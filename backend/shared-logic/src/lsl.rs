@@ -1,58 +1,532 @@
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use chrono::{DateTime, Utc};
-use log::{error, info};
+use log::{error, info, warn};
 use lsl::{resolve_bypred, Pullable, StreamInlet};
 use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast::Sender;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::watch;
 use tokio_util::sync::CancellationToken;
 // use crate::signal_processing::signal_processor::SignalProcessor;
-use crate::pipeline::{Pipeline, PreprocessingConfig, WindowConfig};
+use crate::models::{ClockOffset, PipelineChange, StreamGap};
+use crate::pipeline::{MLConfig, Pipeline, PreprocessingConfig, WindowConfig};
+use crate::signal_processing::blinks::{BlinkConfig, BlinkDetector, BlinkEvent};
+use crate::signal_processing::filters::StreamingBandpass;
 use crate::signal_processing::pipeline_gateway::{PipelineGateway, PipelineOutput};
+use crate::signal_processing::quality::{assess_window, ChannelQuality, QualityConfig};
+use crate::signal_processing::spectrum::{band_power, BandPower, BandPowerConfig};
+use crate::source::{EegSource, PullBuffer, SourceConfig};
 
 pub type ProcessingConfig = PreprocessingConfig;
 pub type WindowingConfig = WindowConfig;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+// How often the clock offset is re-measured during a session
+const CLOCK_SYNC_INTERVAL: Duration = Duration::from_secs(5);
+// Time allowed for the first time_correction estimate (later calls return immediately)
+const TIME_CORRECTION_TIMEOUT_SECS: f64 = 2.0;
+// Weight of a new measurement in the smoothed offset
+const CLOCK_SMOOTHING: f64 = 0.1;
+// Offset changes larger than this are clock steps (LSL clock reset, NTP jump) and are applied at once
+const CLOCK_STEP_SECS: f64 = 0.5;
+
+// How long to wait for a matching stream when the selector doesn't say
+pub const DEFAULT_RESOLVE_TIMEOUT_SECS: f64 = 10.0;
+// How long a stream may go without samples before it is considered lost, when the selector doesn't say
+pub const DEFAULT_LOST_AFTER_SECS: f64 = 5.0;
+// Longest wait between attempts to find a lost stream again
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+// Chooses which LSL stream a session records from. Every field that is set has to match.
+// `predicate` is a raw XPath predicate (e.g. "name='Muse' and starts-with(hostname,'lab')")
+// and replaces the default type='EEG' filter, so non-EEG typed streams can be selected too.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct StreamSelector {
+    pub name: Option<String>,
+    pub source_id: Option<String>,
+    pub hostname: Option<String>,
+    pub predicate: Option<String>,
+    pub resolve_timeout_secs: Option<f64>,
+    // seconds without samples after which the stream is re-resolved
+    pub lost_after_secs: Option<f64>,
+}
+
+impl StreamSelector {
+    // Builds the predicate passed to resolve_bypred.
+    pub fn to_predicate(&self) -> Result<String, String> {
+        let mut clauses = match &self.predicate {
+            Some(predicate) if !predicate.trim().is_empty() => vec![format!("({})", predicate)],
+            _ => vec!["type='EEG'".to_string()],
+        };
+
+        for (field, value) in [
+            ("name", &self.name),
+            ("source_id", &self.source_id),
+            ("hostname", &self.hostname),
+        ] {
+            if let Some(value) = value {
+                // XPath 1.0 string literals have no escape sequence
+                if value.contains('\'') {
+                    return Err(format!("Stream {} can't contain a single quote", field));
+                }
+                clauses.push(format!("{}='{}'", field, value));
+            }
+        }
+
+        Ok(clauses.join(" and "))
+    }
+
+    pub fn resolve_timeout(&self) -> Result<f64, String> {
+        match self.resolve_timeout_secs {
+            None => Ok(DEFAULT_RESOLVE_TIMEOUT_SECS),
+            Some(t) if t.is_finite() && t > 0.0 => Ok(t),
+            Some(t) => Err(format!("Invalid stream resolve timeout: {}", t)),
+        }
+    }
+
+    pub fn lost_after(&self) -> Result<Duration, String> {
+        match self.lost_after_secs {
+            None => Ok(Duration::from_secs_f64(DEFAULT_LOST_AFTER_SECS)),
+            Some(t) if t.is_finite() && t > 0.0 => Ok(Duration::from_secs_f64(t)),
+            Some(t) => Err(format!("Invalid stream lost timeout: {}", t)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct EEGDataPacket {
     pub timestamps: Vec<DateTime<Utc>>,
     pub signals: Vec<Vec<f64>>,
     pub ml_result: Option<PipelineOutput>,
+    // bandpassed copy of signals when the pipeline filters, empty otherwise;
+    // signals stay raw so they can be stored and exported as recorded
+    #[serde(default)]
+    pub filtered: Vec<Vec<f64>>,
+    // how late the filtered samples are: filtered[i] is the signal at
+    // timestamps[i] - filter_delay_secs
+    #[serde(default)]
+    pub filter_delay_secs: f64,
+    // number of leading samples repeated from the previous window (windowing overlap),
+    // the samples after them are new
+    #[serde(default)]
+    pub overlap_len: usize,
+    // contact quality of each channel, rated on the window's new samples before filtering
+    #[serde(default)]
+    pub quality: Vec<ChannelQuality>,
+    // blinks that ended in the window's new samples, when the pipeline detects them
+    #[serde(default)]
+    pub blinks: Vec<BlinkEvent>,
+    // power in each band of the pipeline's bandPower node, per channel, over the whole
+    // (filtered) window
+    #[serde(default)]
+    pub band_power: Vec<Vec<BandPower>>,
+}
+
+impl EEGDataPacket {
+    // The signals the pipeline works on: the filtered copy if there is one, else the raw samples.
+    pub fn processed_signals(&self) -> &[Vec<f64>] {
+        if self.filtered.is_empty() {
+            &self.signals
+        } else {
+            &self.filtered
+        }
+    }
+}
+
+// A change in the session's history, handed over as soon as it happens so it can be stored
+// even if the session later dies.
+#[derive(Debug, Clone)]
+pub enum HistoryEntry {
+    ClockOffsets(Vec<ClockOffset>),
+    Gap(StreamGap),
+    PipelineChange(PipelineChange),
+}
+
+// Statistics for one EEG session, returned once collection stops.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct SessionStats {
+    pub packets_sent: u32,
+    // packets or pulls lost to errors
+    pub dropped: u32,
+    pub samples_received: u64,
+    pub chunks_pulled: u64,
+    pub largest_chunk: usize,
+    pub elapsed_secs: f64,
+    // every clock offset applied to the sample timestamps, oldest first
+    pub clock_offsets: Vec<ClockOffset>,
+    // outages where the stream was lost and had to be found again
+    pub gaps: Vec<StreamGap>,
+    // every pipeline the session ran with, stamped with the first sample it applied to
+    pub pipeline_history: Vec<PipelineChange>,
+    // where the history above is also sent as it is recorded, if anywhere
+    #[serde(skip)]
+    history_tx: Option<UnboundedSender<HistoryEntry>>,
+}
+
+impl SessionStats {
+    fn record_chunk(&mut self, n_samples: usize) {
+        if n_samples == 0 {
+            return;
+        }
+        self.samples_received += n_samples as u64;
+        self.chunks_pulled += 1;
+        self.largest_chunk = self.largest_chunk.max(n_samples);
+    }
+
+    fn record_send(&mut self, result: Result<(), String>) {
+        match result {
+            Ok(_) => self.packets_sent += 1,
+            Err(e) => {
+                error!("Process/send error: {}", e);
+                self.dropped += 1;
+            }
+        }
+    }
+
+    fn record_pipeline(&mut self, applied_at: DateTime<Utc>, pipeline: &Pipeline) {
+        match serde_json::to_value(pipeline) {
+            Ok(pipeline) => {
+                let change = PipelineChange {
+                    applied_at,
+                    pipeline,
+                };
+                self.send_history(HistoryEntry::PipelineChange(change.clone()));
+                self.pipeline_history.push(change);
+            }
+            Err(e) => error!("Failed to record pipeline change: {}", e),
+        }
+    }
+
+    fn record_gap(&mut self, gap: StreamGap) {
+        self.send_history(HistoryEntry::Gap(gap.clone()));
+        self.gaps.push(gap);
+    }
+
+    fn record_clock_offsets(&mut self, offsets: Vec<ClockOffset>) {
+        if offsets.is_empty() {
+            return;
+        }
+        self.send_history(HistoryEntry::ClockOffsets(offsets.clone()));
+        self.clock_offsets.extend(offsets);
+    }
+
+    fn send_history(&self, entry: HistoryEntry) {
+        if let Some(history_tx) = &self.history_tx {
+            if history_tx.send(entry).is_err() {
+                error!("Session history receiver is gone, the entry is only kept in the stats");
+            }
+        }
+    }
+
+    // Samples per second pulled from the inlet over the whole session
+    pub fn throughput(&self) -> f64 {
+        if self.elapsed_secs > 0.0 {
+            self.samples_received as f64 / self.elapsed_secs
+        } else {
+            0.0
+        }
+    }
+}
+
+// What is applied to every full window before it is sent. Rebuilt from the client's
+// pipeline when it is replaced mid-session.
+struct WindowProcessing {
+    config: ProcessingConfig,
+    ml: Option<MLConfig>,
+    bandpass: Option<StreamingBandpass>,
+    gateway: Option<PipelineGateway>,
+    quality: QualityConfig,
+    blinks: Option<BlinkDetector>,
+    band_power: Option<BandPowerConfig>,
+}
+
+impl WindowProcessing {
+    // Takes the preprocessing and ML settings of a new pipeline. The channel count stays the
+    // stream's, and the bandpass keeps its state unless the new config changes its design.
+    // Returns whether the bandpass was replaced.
+    fn apply_pipeline(&mut self, pipeline: &Pipeline) -> Result<bool, String> {
+        let mut config = pipeline.preprocessing_config().cloned().unwrap_or_default();
+        config.n_channels = self.config.n_channels;
+        let filter_changed = config != self.config;
+        if filter_changed {
+            self.bandpass = build_bandpass(&config)?;
+        }
+        // the blink detector keeps its baseline unless its settings change
+        let blinks = pipeline.blink_config();
+        let unchanged = self.blinks.as_ref().is_some_and(|detector| {
+            Some(detector.config()) == blinks && detector.sample_rate() == config.sfreq as f64
+        });
+        if !unchanged {
+            self.blinks = build_blink_detector(blinks, &config)?;
+        }
+        self.config = config;
+        self.ml = pipeline.ml_config().cloned();
+        self.quality = pipeline.quality_config().cloned().unwrap_or_default();
+        self.band_power = pipeline.band_power_config().cloned();
+        Ok(filter_changed)
+    }
+}
+
+// Designs the session's bandpass filter, None if the config has it turned off.
+fn build_bandpass(config: &ProcessingConfig) -> Result<Option<StreamingBandpass>, String> {
+    if !config.apply_bandpass {
+        return Ok(None);
+    }
+    StreamingBandpass::from_config(config)
+        .map(Some)
+        .map_err(|e| format!("Failed to design bandpass filter: {}", e))
+}
+
+// Sets up the session's blink detector, None if the pipeline has no blink detection node.
+fn build_blink_detector(
+    config: Option<&BlinkConfig>,
+    processing: &ProcessingConfig,
+) -> Result<Option<BlinkDetector>, String> {
+    config
+        .map(|c| BlinkDetector::new(c.clone(), processing.sfreq as f64, processing.n_channels))
+        .transpose()
+        .map_err(|e| format!("Failed to set up blink detection: {}", e))
+}
+
+// Maps the remote LSL timestamps of an inlet to Unix time. Both the remote -> local LSL
+// clock correction and the local LSL clock -> Unix offset drift over long sessions, so they
+// are re-measured every CLOCK_SYNC_INTERVAL and smoothed before being applied.
+#[derive(Default)]
+pub(crate) struct ClockSync {
+    applied_offset: Option<f64>,
+    time_correction: f64,
+    last_sync: Option<Instant>,
+    history: Vec<ClockOffset>,
+}
+
+impl ClockSync {
+    // Re-measures the offset once CLOCK_SYNC_INTERVAL has passed since the last measurement.
+    pub(crate) fn maybe_sync(&mut self, inlet: &StreamInlet) {
+        if self
+            .last_sync
+            .is_some_and(|last| last.elapsed() < CLOCK_SYNC_INTERVAL)
+        {
+            return;
+        }
+        self.last_sync = Some(Instant::now());
+
+        match inlet.time_correction(TIME_CORRECTION_TIMEOUT_SECS) {
+            Ok(correction) => self.time_correction = correction,
+            Err(e) => warn!(
+                "LSL time correction failed, keeping {:.6}s: {}",
+                self.time_correction, e
+            ),
+        }
+        let unix_offset =
+            Utc::now().timestamp_nanos_opt().unwrap() as f64 / 1_000_000_000.0 - lsl::local_clock();
+        self.apply(Utc::now(), unix_offset, inlet.was_clock_reset());
+    }
+
+    // Folds a new measurement into the applied offset and records it.
+    fn apply(&mut self, measured_at: DateTime<Utc>, unix_offset: f64, clock_reset: bool) {
+        let measured = self.time_correction + unix_offset;
+        let applied = match self.applied_offset {
+            Some(previous) if !clock_reset && (measured - previous).abs() < CLOCK_STEP_SECS => {
+                previous + CLOCK_SMOOTHING * (measured - previous)
+            }
+            Some(previous) => {
+                warn!(
+                    "Clock offset stepped from {:.6}s to {:.6}s, applying immediately",
+                    previous, measured
+                );
+                measured
+            }
+            None => measured,
+        };
+        self.applied_offset = Some(applied);
+        self.history.push(ClockOffset {
+            measured_at,
+            time_correction: self.time_correction,
+            unix_offset,
+            applied_offset: applied,
+        });
+    }
+
+    // Seconds to add to a remote LSL timestamp to get Unix time
+    pub(crate) fn offset(&self) -> f64 {
+        self.applied_offset.unwrap_or(0.0)
+    }
+
+    // Starts over for a new inlet, whose clock has nothing to do with the old one.
+    fn restart(&mut self) {
+        self.applied_offset = None;
+        self.time_correction = 0.0;
+        self.last_sync = None;
+    }
+}
+
+// An LSL inlet as a session's EEG source, plus what is needed to find the stream again if it
+// disappears. Its timestamps are on the sending machine's LSL clock, mapped to Unix time by ClockSync.
+pub struct LslSource {
+    inlet: StreamInlet,
+    selector: StreamSelector,
+    n_channels: usize,
+    lost_after: Duration,
+    clock: ClockSync,
+    // scratch space for a single sample
+    sample: Vec<f32>,
+}
+
+impl LslSource {
+    pub fn connect(selector: StreamSelector) -> Result<Self, String> {
+        let lost_after = selector.lost_after()?;
+        let (inlet, n_channels) = setup_eeg_stream(&selector)?;
+        Ok(Self {
+            inlet,
+            selector,
+            n_channels,
+            lost_after,
+            clock: ClockSync::default(),
+            sample: vec![0.0; n_channels],
+        })
+    }
+}
+
+impl EegSource for LslSource {
+    fn n_channels(&self) -> usize {
+        self.n_channels
+    }
+
+    // Waits up to `timeout` for the first sample, then drains the inlet without blocking.
+    fn pull_chunk(&mut self, buffer: &mut PullBuffer, timeout: f64) -> Result<usize, String> {
+        buffer.clear();
+
+        let mut wait = timeout;
+        while !buffer.is_full() {
+            let timestamp = self
+                .inlet
+                .pull_sample_buf(&mut self.sample, wait)
+                .map_err(|e| e.to_string())?;
+            if timestamp == 0.0 {
+                break; // no more data
+            }
+            buffer.push(timestamp, &self.sample);
+            wait = 0.0;
+        }
+        Ok(buffer.len())
+    }
+
+    fn clock_offset(&mut self) -> f64 {
+        self.clock.maybe_sync(&self.inlet);
+        self.clock.offset()
+    }
+
+    fn take_clock_history(&mut self) -> Vec<ClockOffset> {
+        std::mem::take(&mut self.clock.history)
+    }
+
+    fn lost_after(&self) -> Duration {
+        self.lost_after
+    }
+
+    // Re-resolves the stream with the same selector, backing off between attempts,
+    // until it is back or the session is cancelled. Returns false if cancelled first.
+    fn reconnect(&mut self, cancel_token: &CancellationToken) -> bool {
+        let mut attempt = 0;
+        while !cancel_token.is_cancelled() {
+            match setup_eeg_stream(&self.selector) {
+                Ok((inlet, n_channels)) if n_channels == self.n_channels => {
+                    info!("EEG stream is back after {} attempt(s)", attempt + 1);
+                    self.inlet = inlet;
+                    // the new inlet's clock has nothing to do with the old one
+                    self.clock.restart();
+                    return true;
+                }
+                Ok((_, n_channels)) => warn!(
+                    "Found a stream with {} channels but the session records {}, still looking",
+                    n_channels, self.n_channels
+                ),
+                Err(e) => warn!("Reconnect attempt {} failed: {}", attempt + 1, e),
+            }
+
+            // Sleep in small steps so cancellation isn't held up by the backoff
+            let delay = reconnect_delay(attempt);
+            let started = Instant::now();
+            while started.elapsed() < delay && !cancel_token.is_cancelled() {
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            attempt += 1;
+        }
+        false
+    }
+}
+
+// Wait before the next reconnect attempt: 1s, 2s, 4s, ... up to MAX_RECONNECT_DELAY.
+fn reconnect_delay(attempt: u32) -> Duration {
+    Duration::from_secs(1 << attempt.min(5)).min(MAX_RECONNECT_DELAY)
+}
+
+// Samples carried over from the end of one window to the front of the next.
+#[derive(Default)]
+struct Overlap {
+    signals: Vec<Vec<f64>>,
+    filtered: Vec<Vec<f64>>,
+    timestamps: Vec<DateTime<Utc>>,
+}
+
+impl Overlap {
+    fn clear(&mut self) {
+        self.signals.clear();
+        self.filtered.clear();
+        self.timestamps.clear();
+    }
+
+    // Puts the carried samples, with the timestamps they were recorded at, in front of the packet.
+    fn prepend_to(&self, packet: &mut EEGDataPacket) {
+        if self.timestamps.is_empty() {
+            return;
+        }
+        for (ch, carried) in packet.signals.iter_mut().zip(&self.signals) {
+            ch.splice(0..0, carried.iter().copied());
+        }
+        for (ch, carried) in packet.filtered.iter_mut().zip(&self.filtered) {
+            ch.splice(0..0, carried.iter().copied());
+        }
+        packet
+            .timestamps
+            .splice(0..0, self.timestamps.iter().copied());
+        packet.overlap_len = self.timestamps.len();
+    }
+
+    // Keeps the last overlap_size samples of the packet for the next window.
+    fn keep_tail(&mut self, packet: &EEGDataPacket, overlap_size: usize) {
+        let n = packet.timestamps.len();
+        let keep = overlap_size.min(n);
+        let tail =
+            |channels: &[Vec<f64>]| channels.iter().map(|ch| ch[n - keep..].to_vec()).collect();
+        self.signals = tail(&packet.signals);
+        self.filtered = tail(&packet.filtered);
+        self.timestamps = packet.timestamps[n - keep..].to_vec();
+    }
 }
 
 // Async entry point for EEG data collection.
+// The pipeline comes through pipeline_rx, so the client can replace it mid-session;
+// changes are applied at the next window boundary. Clock offsets, gaps and pipeline
+// changes are sent to history_tx as they happen.
 pub async fn receive_eeg(
     tx: Sender<Arc<EEGDataPacket>>,
     cancel_token: CancellationToken,
-    pipeline: Pipeline,
-) {
+    pipeline_rx: watch::Receiver<Pipeline>,
+    source_config: SourceConfig,
+    history_tx: Option<UnboundedSender<HistoryEntry>>,
+) -> Result<SessionStats, String> {
     info!("Starting EEG data receiver");
 
     // Extract configs from the pipeline, falling back to defaults if a node is missing
-    let preprocessing_config = pipeline.preprocessing_config().cloned().unwrap_or_default();
-    let window_config = pipeline.window_config().cloned().unwrap_or_default();
+    let pipeline = pipeline_rx.borrow().clone();
+    let mut processing_config = pipeline.preprocessing_config().cloned().unwrap_or_default();
     info!(
         "Received preprocessing config: apply_bandpass={}, l_freq={:?}, h_freq={:?}",
-        preprocessing_config.apply_bandpass,
-        preprocessing_config.l_freq,
-        preprocessing_config.h_freq
+        processing_config.apply_bandpass, processing_config.l_freq, processing_config.h_freq
     );
-
-    // Create a watch channel from the initial window config.
-    // The receiver is passed into the collection loop so it can react to future updates.
-    let (_windowing_tx, windowing_rx) = tokio::sync::watch::channel(window_config);
-
-    receive_eeg_with_config(tx, cancel_token, preprocessing_config, windowing_rx).await;
-}
-
-// Async entry point for EEG data collection.
-pub async fn receive_eeg_with_config(
-    tx: Sender<Arc<EEGDataPacket>>,
-    cancel_token: CancellationToken,
-    processing_config: ProcessingConfig,
-    windowing_rx: tokio::sync::watch::Receiver<WindowingConfig>,
-) {
-    info!("Starting EEG data receiver");
     // let python_script_path = std::env::var("SIGNAL_PROCESSING_SCRIPT")
     //     .unwrap_or_else(|_| "../shared-logic/src/signal_processing/signalProcessing.py".to_string());
 
@@ -61,248 +535,451 @@ pub async fn receive_eeg_with_config(
     });
 
     let result = tokio::task::spawn_blocking(move || {
-        // Setup pipeline gateway (replaces SignalProcessor)
-        // let sig_processor = match SignalProcessor::new(&python_script_path) { ... };
+        // Bandpass filtering runs natively, so it does not depend on the Python side
+        let bandpass = build_bandpass(&processing_config)?;
+
+        // Setup pipeline gateway (replaces SignalProcessor).
+        // Without it the session still records and filters, it just has no ML output.
         let gateway = match PipelineGateway::new(&manager_script_path) {
-            Ok(g) => g,
+            Ok(g) => Some(g),
             Err(e) => {
                 info!("current path: {:?}", std::env::current_dir());
                 info!("Looking for manager script at: {}", manager_script_path);
                 error!("Failed to initialize pipeline gateway: {}", e);
-                return (0, 0);
+                warn!("Continuing without ML classification");
+                None
             }
         };
 
-        // Setup stream and inlet
-        let inlet = match setup_eeg_stream() {
-            Ok(inlet) => inlet,
-            Err(e) => {
-                error!("Failed to setup EEG stream: {}", e);
-                return (0, 0);
-            }
+        // Open the source, it is created here because an LSL inlet can't leave this thread
+        let source = source_config
+            .connect()
+            .map_err(|e| format!("Failed to setup EEG stream: {}", e))?;
+
+        // The source is the source of truth for the channel count
+        if source.n_channels() != processing_config.n_channels {
+            info!(
+                "EEG source has {} channels (config specified {}), using the source's count",
+                source.n_channels(),
+                processing_config.n_channels
+            );
+        }
+        processing_config.n_channels = source.n_channels();
+        let blinks = build_blink_detector(pipeline.blink_config(), &processing_config)?;
+
+        let processing = WindowProcessing {
+            config: processing_config,
+            ml: pipeline.ml_config().cloned(),
+            bandpass,
+            gateway,
+            quality: pipeline.quality_config().cloned().unwrap_or_default(),
+            blinks,
+            band_power: pipeline.band_power_config().cloned(),
         };
 
         // Run collection loop
-        run_eeg_collection(
-            inlet,
+        Ok(run_eeg_collection(
+            source,
             tx,
             cancel_token,
-            processing_config,
-            gateway,
-            windowing_rx,
-        )
+            processing,
+            pipeline_rx,
+            history_tx,
+        ))
     });
 
     // Handle results
     match result.await {
-        Ok((count, drop)) => {
+        Ok(Ok(stats)) => {
             info!(
-                "EEG session completed - received: {}, dropped: {}",
-                count, drop
+                "EEG session completed - sent: {}, dropped: {}, samples: {} in {:.1}s ({:.1} samples/s, {} chunks, largest {})",
+                stats.packets_sent,
+                stats.dropped,
+                stats.samples_received,
+                stats.elapsed_secs,
+                stats.throughput(),
+                stats.chunks_pulled,
+                stats.largest_chunk
             );
+            Ok(stats)
+        }
+        Ok(Err(e)) => {
+            error!("{}", e);
+            Err(e)
         }
         Err(e) => {
             error!("EEG receiver task panicked: {}", e);
+            Err(format!("EEG receiver task panicked: {}", e))
         }
     }
 }
 
-// Resolves EEG stream and creates inlet for data reception.
-// Returns error if no streams found or inlet creation fails.
-fn setup_eeg_stream() -> Result<StreamInlet, String> {
-    let streams = resolve_bypred("type='EEG'", 1, lsl::FOREVER)
+// Resolves the EEG stream matching the selector and creates inlet for data reception.
+// Returns the inlet along with the stream's channel count.
+// Returns error if no matching stream shows up within the timeout or inlet creation fails.
+fn setup_eeg_stream(selector: &StreamSelector) -> Result<(StreamInlet, usize), String> {
+    let predicate = selector.to_predicate()?;
+    let timeout = selector.resolve_timeout()?;
+    info!(
+        "Resolving EEG stream matching \"{}\" (timeout {}s)",
+        predicate, timeout
+    );
+
+    let streams = resolve_bypred(&predicate, 1, timeout)
         .map_err(|e| format!("Could not resolve EEG stream: {}", e))?;
 
     if streams.is_empty() {
-        return Err("No EEG streams found".to_string());
+        return Err(format!(
+            "No EEG stream matching \"{}\" found within {}s",
+            predicate, timeout
+        ));
     }
 
-    info!("EEG stream found, creating inlet");
-    StreamInlet::new(&streams[0], 1000, 0, true)
-        .map_err(|e| format!("Could not create StreamInlet: {}", e))
+    let n_channels = usize::try_from(streams[0].channel_count())
+        .ok()
+        .filter(|&n| n > 0)
+        .ok_or_else(|| "EEG stream reports no channels".to_string())?;
+
+    info!(
+        "EEG stream found: {} from {} ({} channels), creating inlet",
+        streams[0].stream_name(),
+        streams[0].hostname(),
+        n_channels
+    );
+    let inlet = StreamInlet::new(&streams[0], 1000, 0, true)
+        .map_err(|e| format!("Could not create StreamInlet: {}", e))?;
+    Ok((inlet, n_channels))
 }
 
 // Main EEG data collection loop.
-// Returns (successful_count, dropped_count) statistics.
+// If the source goes quiet for longer than its lost_after interval it is reconnected
+// and the session continues, with the outage recorded as a gap.
+// Returns the session statistics, including the measured pull throughput.
 fn run_eeg_collection(
-    inlet: StreamInlet,
+    mut source: Box<dyn EegSource>,
     tx: Sender<Arc<EEGDataPacket>>,
     cancel_token: CancellationToken,
-    config: ProcessingConfig,
-    gateway: PipelineGateway,
-    windowing_rx: tokio::sync::watch::Receiver<WindowingConfig>,
-) -> (u32, u32) {
-    let mut count = 0;
-    let mut drop = 0;
+    mut processing: WindowProcessing,
+    mut pipeline_rx: watch::Receiver<Pipeline>,
+    history_tx: Option<UnboundedSender<HistoryEntry>>,
+) -> SessionStats {
+    let mut stats = SessionStats {
+        history_tx,
+        ..Default::default()
+    };
+    let started = Instant::now();
 
-    let mut windowing = windowing_rx.borrow().clone();
+    // The initial pipeline is stamped into the history like any later change
+    let mut pending_change = Some(pipeline_rx.borrow_and_update().clone());
+    let mut windowing = pending_change
+        .as_ref()
+        .and_then(|p| p.window_config().cloned())
+        .unwrap_or_default();
 
-    // Creates a buffer that stores overlapping eeg samples
-    let mut overlap_buffer: Vec<Vec<f64>> = vec![Vec::new(); 4];
+    // Creates a buffer that stores overlapping eeg samples, along with their original timestamps
+    let mut overlap = Overlap::default();
 
+    let n_channels = processing.config.n_channels;
     let mut packet = EEGDataPacket {
-        timestamps: Vec::with_capacity(windowing.chunk_size + 1),
-        signals: (0..4)
-            .map(|_| Vec::with_capacity(windowing.chunk_size + 1))
+        timestamps: Vec::with_capacity(windowing.chunk_size + windowing.overlap_size),
+        signals: (0..n_channels)
+            .map(|_| Vec::with_capacity(windowing.chunk_size + windowing.overlap_size))
             .collect::<Vec<_>>(),
-        ml_result: None,
+        ..Default::default()
     };
 
-    // Calculate the offset between LSL clock and Unix epoch
-    let lsl_to_unix_offset =
-        Utc::now().timestamp_nanos_opt().unwrap() as f64 / 1_000_000_000.0 - lsl::local_clock();
+    // Samples are pulled in chunks into this buffer, then copied into windows in bulk
+    let mut pull_buffer = PullBuffer::new(n_channels);
+
+    // For detecting a lost stream and recording how long it was gone
+    let mut last_sample_at = Instant::now();
+    let mut last_sample_time: Option<DateTime<Utc>> = None;
+    let mut gap_start: Option<DateTime<Utc>> = None;
     loop {
-        if windowing_rx.has_changed().unwrap_or(false) {
-            windowing = windowing_rx.borrow().clone();
-            info!(
-                "Windowing config updated: chunk={}, overlap={}",
-                windowing.chunk_size, windowing.overlap_size
-            );
-            // Discard old buffer and start fresh with new config
-            packet.timestamps.clear();
-            for ch in &mut packet.signals {
-                ch.clear();
-            }
-            for ch in &mut overlap_buffer {
-                ch.clear();
+        // Pipeline changes only take effect between windows
+        if packet.timestamps.is_empty() {
+            if let Some(pipeline) = take_pipeline_update(
+                &mut pipeline_rx,
+                &mut processing,
+                &mut windowing,
+                &mut overlap,
+            ) {
+                pending_change = Some(pipeline);
             }
         }
 
-        // Check for cancellation
-        if cancel_token.is_cancelled() {
-            info!("EEG data receiver cancelled.");
+        // Check for cancellation, or the end of a source that has one
+        let finished = source.finished();
+        if cancel_token.is_cancelled() || finished {
+            if finished {
+                info!("EEG source has no more samples.");
+            } else {
+                info!("EEG data receiver cancelled.");
+            }
             // Send any remaining samples before exiting
             if !packet.timestamps.is_empty() {
-                match process_and_send(&mut packet, &gateway, &config, &tx) {
-                    Ok(_) => count += 1,
-                    Err(e) => {
-                        error!("Process/send error: {}", e);
-                        drop += 1;
-                    }
-                }
+                stats.record_send(send_window(
+                    &mut packet,
+                    &mut overlap,
+                    windowing.overlap_size,
+                    &mut processing,
+                    &tx,
+                ));
             }
             break;
         }
 
-        // Pull sample with timeout of 1 sec. If it does not see data for 1s, it returns.
-        match inlet.pull_sample(1.0) {
-            Ok((sample, timestamp)) => {
-                match accumulate_sample(
-                    &sample,
-                    timestamp + lsl_to_unix_offset,
-                    &mut packet,
-                    windowing.chunk_size,
-                ) {
-                    Ok(true) => {
-                        // Window is full. Prepend overlap from previous window if there are any
-                        if windowing.overlap_size > 0 && !overlap_buffer[0].is_empty() {
-                            // Insert overlap samples at the front of the packet
-                            for (ch_idx, ch) in packet.signals.iter_mut().enumerate() {
-                                let mut new_ch = overlap_buffer[ch_idx].clone();
-                                new_ch.extend_from_slice(ch);
-                                *ch = new_ch;
-                            }
-
-                            // Timestamps: prepend placeholders (or track overlap timestamps)
-                            // For simplicity, pad with copies of the first timestamp
-                            let first_ts = packet.timestamps[0];
-                            let mut new_ts = vec![first_ts; windowing.overlap_size];
-                            new_ts.extend_from_slice(&packet.timestamps);
-                            packet.timestamps = new_ts;
-                        }
-
-                        // Save the tail as the new overlap_buffer
-                        let n = packet.signals[0].len();
-                        let keep = windowing.overlap_size.min(n);
-                        for (ch_idx, ch) in packet.signals.iter().enumerate() {
-                            overlap_buffer[ch_idx] = ch[n - keep..].to_vec();
-                        }
-
-                        // Packet is full, send it
-                        info!(
-                            "Packet is full, sending window: {} samples (overlap: {})",
-                            packet.signals[0].len(),
-                            keep
-                        );
-                        match process_and_send(&mut packet, &gateway, &config, &tx) {
-                            Ok(_) => count += 1,
-                            Err(e) => {
-                                error!("Process/send error: {}", e);
-                                drop += 1;
-                            }
-                        }
-                        packet.timestamps.clear();
-                        for channel in &mut packet.signals {
-                            channel.clear();
-                        }
-                    }
-                    Ok(false) => {} // Sample added, but packet not full yet
-                    Err(e) => {
-                        let error_msg = e.to_string();
-                        if error_msg.contains("Invalid sample length: got 0 channels") {
-                            info!("Received empty sample from LSL stream (likely during shutdown) - ignoring");
-                        } else {
-                            drop += 1;
-                            error!("Sample processing error (drop #{}): {}", drop, e);
-                        }
-                    }
-                }
-            }
+        // Offset between the source's clock and Unix epoch, kept up to date by the source
+        let offset = source.clock_offset();
+        stats.record_clock_offsets(source.take_clock_history());
+
+        // Pull everything that has arrived, waiting up to 1 sec for the first sample.
+        let n_pulled = match source.pull_chunk(&mut pull_buffer, 1.0) {
+            Ok(n) => n,
             Err(e) => {
                 let error_msg = e.to_string();
                 // Only log non-timeout errors
                 if !error_msg.contains("timeout") {
-                    error!("LSL pull_sample error: {}", e);
-                    drop += 1;
+                    error!("LSL pull error: {}", e);
+                    stats.dropped += 1;
                 }
+                0
             }
+        };
+
+        if n_pulled == 0 {
+            if last_sample_at.elapsed() >= source.lost_after() {
+                warn!(
+                    "No EEG samples for {:.1}s, stream lost. Reconnecting...",
+                    last_sample_at.elapsed().as_secs_f64()
+                );
+
+                // Send what was collected before the outage, the next window starts fresh
+                if !packet.timestamps.is_empty() {
+                    stats.record_send(send_window(
+                        &mut packet,
+                        &mut overlap,
+                        windowing.overlap_size,
+                        &mut processing,
+                        &tx,
+                    ));
+                }
+                overlap.clear();
+                if let Some(filter) = processing.bandpass.as_mut() {
+                    filter.reset();
+                }
+                if let Some(detector) = processing.blinks.as_mut() {
+                    detector.reset();
+                }
+
+                // An outage that already started (stream came back but stayed silent) keeps its start
+                gap_start = gap_start.or(last_sample_time).or_else(|| Some(Utc::now()));
+                source.reconnect(&cancel_token);
+                last_sample_at = Instant::now();
+            }
+            continue;
+        }
+        stats.record_chunk(n_pulled);
+        last_sample_at = Instant::now();
+
+        // The first samples after an outage close its gap
+        if let Some(start) = gap_start.take() {
+            let end = unix_secs_to_datetime(pull_buffer.timestamps()[0] + offset);
+            info!("EEG stream resumed, gap from {} to {}", start, end);
+            stats.record_gap(StreamGap {
+                gap_start: start,
+                gap_end: end,
+            });
+        }
+        last_sample_time = Some(unix_secs_to_datetime(
+            pull_buffer.timestamps()[n_pulled - 1] + offset,
+        ));
+
+        // Fill windows from the chunk, sending each one as soon as it is full
+        let mut next = 0;
+        while next < n_pulled {
+            // A new pipeline is in effect from the first sample of the window after it arrived
+            if packet.timestamps.is_empty() {
+                if let Some(pipeline) = pending_change.take() {
+                    stats.record_pipeline(
+                        unix_secs_to_datetime(pull_buffer.timestamps()[next] + offset),
+                        &pipeline,
+                    );
+                }
+            }
+
+            let chunk_size = windowing.chunk_size.max(1);
+            next = fill_window(&pull_buffer, next, &mut packet, chunk_size, offset);
+            if packet.timestamps.len() >= chunk_size {
+                stats.record_send(send_window(
+                    &mut packet,
+                    &mut overlap,
+                    windowing.overlap_size,
+                    &mut processing,
+                    &tx,
+                ));
+                if let Some(pipeline) = take_pipeline_update(
+                    &mut pipeline_rx,
+                    &mut processing,
+                    &mut windowing,
+                    &mut overlap,
+                ) {
+                    pending_change = Some(pipeline);
+                }
+            }
+        }
+    }
+
+    // Session ended while the stream was gone
+    if let Some(start) = gap_start {
+        stats.record_gap(StreamGap {
+            gap_start: start,
+            gap_end: Utc::now(),
+        });
+    }
+
+    stats.elapsed_secs = started.elapsed().as_secs_f64();
+    stats.record_clock_offsets(source.take_clock_history());
+    // the history is complete, let its receiver finish
+    stats.history_tx = None;
+    stats
+}
+
+// Applies the pipeline the client sent since the last check, if any. Only called between
+// windows, so each window is processed with a single config. Returns the applied pipeline.
+fn take_pipeline_update(
+    pipeline_rx: &mut watch::Receiver<Pipeline>,
+    processing: &mut WindowProcessing,
+    windowing: &mut WindowingConfig,
+    overlap: &mut Overlap,
+) -> Option<Pipeline> {
+    if !pipeline_rx.has_changed().unwrap_or(false) {
+        return None;
+    }
+    let pipeline = pipeline_rx.borrow_and_update().clone();
+
+    match processing.apply_pipeline(&pipeline) {
+        // the carried overlap was filtered with the old design, or not at all
+        Ok(true) => overlap.clear(),
+        Ok(false) => {}
+        Err(e) => {
+            error!("Keeping the previous pipeline: {}", e);
+            return None;
+        }
+    }
+
+    let new_windowing = pipeline.window_config().cloned().unwrap_or_default();
+    if new_windowing != *windowing {
+        info!(
+            "Windowing config updated: chunk={}, overlap={}",
+            new_windowing.chunk_size, new_windowing.overlap_size
+        );
+        // The carried overlap was sized for the old windows, and the filter state continues
+        // from its samples, so both start over
+        overlap.clear();
+        if let Some(bandpass) = processing.bandpass.as_mut() {
+            bandpass.reset();
         }
+        *windowing = new_windowing;
     }
-    (count, drop)
+
+    info!("Pipeline updated ({} nodes)", pipeline.nodes.len());
+    Some(pipeline)
 }
 
-// Accumulates LSL sample into EEGDataPacket.
-// Returns Ok(true) when packet reaches 65 samples, Ok(false) otherwise.
-// Requires at least 4 channels in sample.
-fn accumulate_sample(
-    sample: &[f32],
-    timestamp: f64,
+// Appends samples from the pull buffer, starting at `start`, until the packet holds chunk_size samples.
+// Timestamps are shifted by `unix_offset` seconds.
+// Returns the index of the first sample that didn't fit.
+fn fill_window(
+    buffer: &PullBuffer,
+    start: usize,
     packet: &mut EEGDataPacket,
     chunk_size: usize,
-) -> Result<bool, String> {
-    // Validate sample length
-    if sample.len() < 4 {
-        return Err(format!(
-            "Invalid sample length: got {} channels, expected at least 4",
-            sample.len()
-        ));
+    unix_offset: f64,
+) -> usize {
+    let end = (start + chunk_size.saturating_sub(packet.timestamps.len())).min(buffer.len());
+
+    packet.timestamps.extend(
+        buffer.timestamps()[start..end]
+            .iter()
+            .map(|&timestamp| unix_secs_to_datetime(timestamp + unix_offset)),
+    );
+    for (ch_idx, ch_data) in packet.signals.iter_mut().enumerate() {
+        ch_data.extend((start..end).map(|i| buffer.value(i, ch_idx)));
     }
+    end
+}
 
-    // Convert timestamp
-    let timestamp_dt = DateTime::from_timestamp(
-        timestamp as i64,
-        (timestamp.fract() * 1_000_000_000.0) as u32,
-    )
-    .unwrap_or_else(Utc::now);
+// Converts seconds since the Unix epoch to a DateTime, now if it is out of range.
+pub(crate) fn unix_secs_to_datetime(timestamp: f64) -> DateTime<Utc> {
+    let secs = timestamp.floor();
+    DateTime::from_timestamp(secs as i64, ((timestamp - secs) * 1_000_000_000.0) as u32)
+        .unwrap_or_else(Utc::now)
+}
 
-    // info!("Raw timestamp: {}, Converted: {:?}", timestamp, timestamp_dt);
+// Converts a DateTime to seconds since the Unix epoch.
+pub(crate) fn datetime_to_unix_secs(time: DateTime<Utc>) -> f64 {
+    time.timestamp() as f64 + time.timestamp_subsec_nanos() as f64 / 1_000_000_000.0
+}
 
-    // Add sample to packet
-    packet.timestamps.push(timestamp_dt);
-    // Add sample to each channel
-    for (ch_idx, ch_data) in packet.signals.iter_mut().enumerate() {
-        ch_data.push(sample[ch_idx] as f64); // Convert here
+// Handles a full window: rates the new samples and looks for blinks in them, filters a copy
+// of them (continuing from the previous window's filter state, the overlap is already
+// filtered), prepends the previous window's overlap, keeps this window's tail for the next
+// one, takes the band power of the whole window and sends it. The raw samples are kept as
+// they are. The packet is emptied afterwards.
+fn send_window(
+    packet: &mut EEGDataPacket,
+    overlap: &mut Overlap,
+    overlap_size: usize,
+    processing: &mut WindowProcessing,
+    tx: &Sender<Arc<EEGDataPacket>>,
+) -> Result<(), String> {
+    packet.quality = assess_window(
+        &packet.signals,
+        processing.config.sfreq as f64,
+        &processing.quality,
+    );
+    packet.blinks = match processing.blinks.as_mut() {
+        Some(detector) => detector.process(&packet.timestamps, &packet.signals),
+        None => Vec::new(),
+    };
+    if let Some(filter) = processing.bandpass.as_mut() {
+        packet.filtered.clone_from(&packet.signals);
+        filter.process(&mut packet.filtered);
+        packet.filter_delay_secs = filter.group_delay_samples() / processing.config.sfreq as f64;
     }
 
-    // Check if packet is full
-    Ok(packet.signals[0].len() >= chunk_size)
+    overlap.prepend_to(packet);
+    overlap.keep_tail(packet, overlap_size);
+    packet.band_power = match &processing.band_power {
+        Some(config) => band_power(
+            packet.processed_signals(),
+            processing.config.sfreq as f64,
+            config,
+        ),
+        None => Vec::new(),
+    };
+
+    info!(
+        "Packet is full, sending window: {} samples (overlap: {})",
+        packet.timestamps.len(),
+        packet.overlap_len
+    );
+    let result = process_and_send(packet, processing, tx);
+
+    packet.timestamps.clear();
+    for channel in &mut packet.signals {
+        channel.clear();
+    }
+    packet.filtered.clear();
+    packet.overlap_len = 0;
+    result
 }
 
 // calls the Python pipeline manager to process the packet and sends it
 fn process_and_send(
     packet: &mut EEGDataPacket,
-    gateway: &PipelineGateway,
-    config: &ProcessingConfig,
+    processing: &WindowProcessing,
     tx: &Sender<Arc<EEGDataPacket>>,
 ) -> Result<(), String> {
     if packet.timestamps.is_empty() {
@@ -310,48 +987,38 @@ fn process_and_send(
     }
 
     // Call the Python pipeline manager (replaces direct SignalProcessor PyO3 calls)
-    info!("starting pipeline processing");
-    packet.ml_result = match gateway.call_pipeline(config, &packet.signals) {
-        Ok(Some(output)) => {
-            info!(
-                "ML result: task={}, label={}, confidence={:.2}",
-                output.task, output.overall_label, output.confidence
-            );
-            Some(output)
-        }
-        Ok(None) => {
-            info!("Pipeline ran but returned no classifier output");
-            None
-        }
-        Err(e) => {
-            error!("Pipeline gateway error: {}", e);
-            None
+    packet.ml_result = match &processing.gateway {
+        Some(gateway) => {
+            info!("starting pipeline processing");
+            let result = match gateway.call_pipeline(
+                &processing.config,
+                processing.ml.as_ref(),
+                packet.processed_signals(),
+            ) {
+                Ok(Some(output)) => {
+                    info!(
+                        "ML result: task={}, label={}, confidence={:.2}",
+                        output.task, output.overall_label, output.confidence
+                    );
+                    Some(output)
+                }
+                Ok(None) => {
+                    info!("Pipeline ran but returned no classifier output");
+                    None
+                }
+                Err(e) => {
+                    error!("Pipeline gateway error: {}", e);
+                    None
+                }
+            };
+            info!("done pipeline processing");
+            result
         }
+        None => None,
     };
-    info!("done pipeline processing");
-
-    // // Old PyO3 calls via SignalProcessor — replaced by gateway above
-    // if config.apply_bandpass {
-    //     packet.signals = if config.use_iir {
-    //         processor.apply_iir_bandpass(&packet.signals, config.sfreq, config.l_freq, config.h_freq)?
-    //     } else {
-    //         processor.apply_fir_bandpass(&packet.signals, config.sfreq, config.l_freq, config.h_freq)?
-    //     };
-    // }
-
-    //  // Log last 5 samples before filtering
-    // for (ch_idx, channel) in packet.signals.iter().enumerate() {
-    //     let start = channel.len().saturating_sub(5);
-    //     info!("Before ch{}: {:?}", ch_idx, &channel[start..]);
-    // }
-    // // Log last 5 samples after filtering
-    // for (ch_idx, channel) in packet.signals.iter().enumerate() {
-    //     let start = channel.len().saturating_sub(5);
-    //     info!("After ch{}: {:?}", ch_idx, &channel[start..]);
-    // }
 
     // // Apply downsampling
-    // if let Some(factor) = config.downsample_factor {
+    // if let Some(factor) = processing.config.downsample_factor {
     //     packet.signals = processor.downsample(&packet.signals, factor)?;
 
     //     // Adjust timestamps to match downsampled data
@@ -370,3 +1037,466 @@ fn process_and_send(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::Node;
+
+    #[test]
+    fn test_windows_carry_overlap_with_real_timestamps() {
+        let (tx, mut rx) = tokio::sync::broadcast::channel(16);
+        let mut processing = WindowProcessing {
+            config: ProcessingConfig {
+                n_channels: 2,
+                ..Default::default()
+            },
+            ml: None,
+            bandpass: None,
+            gateway: None,
+            quality: QualityConfig::default(),
+            blinks: None,
+            band_power: None,
+        };
+
+        // one pulled chunk of 10 samples, channel 0 holds the sample index
+        let mut buffer = PullBuffer::new(2);
+        for i in 0..10 {
+            buffer.push(1_700_000_000.0 + i as f64, &[i as f32, -(i as f32)]);
+        }
+
+        let mut packet = EEGDataPacket {
+            timestamps: Vec::new(),
+            signals: vec![Vec::new(); 2],
+            ..Default::default()
+        };
+        let mut overlap = Overlap::default();
+        let mut next = 0;
+        while next < buffer.len() {
+            next = fill_window(&buffer, next, &mut packet, 4, 0.0);
+            if packet.timestamps.len() >= 4 {
+                send_window(&mut packet, &mut overlap, 2, &mut processing, &tx).unwrap();
+            }
+        }
+        // the last 2 samples wait for the next pull
+        assert_eq!(packet.timestamps.len(), 2);
+
+        let first = rx.try_recv().unwrap();
+        assert_eq!(first.overlap_len, 0);
+        assert_eq!(first.signals[0], vec![0.0, 1.0, 2.0, 3.0]);
+
+        let second = rx.try_recv().unwrap();
+        assert_eq!(second.overlap_len, 2);
+        assert_eq!(second.signals[0], vec![2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+        assert_eq!(second.signals[1][0], -2.0);
+        assert_eq!(&second.timestamps[..2], &first.timestamps[2..]);
+        assert!(second.timestamps.windows(2).all(|w| w[0] < w[1]));
+
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_bandpass_filters_a_copy_and_keeps_raw_samples() {
+        let (tx, mut rx) = tokio::sync::broadcast::channel(16);
+        let config = ProcessingConfig {
+            n_channels: 1,
+            ..Default::default()
+        };
+        let mut processing = WindowProcessing {
+            bandpass: build_bandpass(&config).unwrap(),
+            config,
+            ml: None,
+            gateway: None,
+            quality: QualityConfig::default(),
+            blinks: None,
+            band_power: None,
+        };
+
+        // a DC offset the bandpass removes
+        let raw: Vec<f64> = (0..512).map(|i| 100.0 + (i % 7) as f64).collect();
+        let mut packet = EEGDataPacket {
+            timestamps: (0..256)
+                .map(|i| unix_secs_to_datetime(1_700_000_000.0 + i as f64 / 256.0))
+                .collect(),
+            signals: vec![raw[..256].to_vec()],
+            ..Default::default()
+        };
+        let mut overlap = Overlap::default();
+        send_window(&mut packet, &mut overlap, 64, &mut processing, &tx).unwrap();
+        packet.timestamps = (256..512)
+            .map(|i| unix_secs_to_datetime(1_700_000_000.0 + i as f64 / 256.0))
+            .collect();
+        packet.signals = vec![raw[256..].to_vec()];
+        send_window(&mut packet, &mut overlap, 64, &mut processing, &tx).unwrap();
+
+        let first = rx.try_recv().unwrap();
+        assert_eq!(first.signals[0], raw[..256]);
+        assert_eq!(first.filtered[0].len(), 256);
+        assert_ne!(first.filtered[0], first.signals[0]);
+        assert_eq!(first.processed_signals(), &first.filtered[..]);
+
+        // the overlap carries both the raw and the filtered samples
+        let second = rx.try_recv().unwrap();
+        assert_eq!(second.overlap_len, 64);
+        assert_eq!(second.signals[0], raw[192..]);
+        assert_eq!(second.filtered[0].len(), 320);
+        assert_eq!(second.filtered[0][..64], first.filtered[0][192..]);
+    }
+
+    #[test]
+    fn test_filter_delay_aligns_filtered_event() {
+        let (tx, mut rx) = tokio::sync::broadcast::channel(16);
+        let config = ProcessingConfig {
+            n_channels: 1,
+            ..Default::default()
+        };
+        let mut processing = WindowProcessing {
+            bandpass: build_bandpass(&config).unwrap(),
+            config,
+            ml: None,
+            gateway: None,
+            quality: QualityConfig::default(),
+            blinks: None,
+            band_power: None,
+        };
+
+        // a spike at sample 300, the causal FIR shows it over 400 samples later
+        let event = 300;
+        let sample_time = |i: usize| 1_700_000_000.0 + i as f64 / 256.0;
+        let mut packet = EEGDataPacket {
+            signals: vec![Vec::new()],
+            ..Default::default()
+        };
+        let mut overlap = Overlap::default();
+        for window in 0..5 {
+            let range = window * 256..(window + 1) * 256;
+            packet.timestamps = range
+                .clone()
+                .map(|i| unix_secs_to_datetime(sample_time(i)))
+                .collect();
+            packet.signals = vec![range
+                .map(|i| if i == event { 100.0 } else { 0.0 })
+                .collect()];
+            send_window(&mut packet, &mut overlap, 0, &mut processing, &tx).unwrap();
+        }
+
+        let windows: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        let (peak_time, delay) = windows
+            .iter()
+            .flat_map(|w| {
+                w.timestamps
+                    .iter()
+                    .zip(&w.filtered[0])
+                    .map(move |(t, v)| (*t, *v, w.filter_delay_secs))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(t, _, delay)| (t, delay))
+            .unwrap();
+        assert!(delay > 1.0);
+
+        let aligned = peak_time.timestamp_micros() as f64 / 1e6 - delay;
+        assert!(
+            (aligned - sample_time(event)).abs() <= 1.0 / 256.0,
+            "filtered event at {}, recorded at {}",
+            aligned,
+            sample_time(event)
+        );
+    }
+
+    // Hands out fixed chunks of samples, then ends the session once they run out.
+    // Sample i is [i, -i] at 1_700_000_000 + i / 2 seconds.
+    struct ScriptedSource {
+        chunks: std::collections::VecDeque<std::ops::Range<usize>>,
+        cancel_token: CancellationToken,
+    }
+
+    impl EegSource for ScriptedSource {
+        fn n_channels(&self) -> usize {
+            2
+        }
+
+        fn pull_chunk(&mut self, buffer: &mut PullBuffer, _timeout: f64) -> Result<usize, String> {
+            buffer.clear();
+            match self.chunks.pop_front() {
+                Some(chunk) => {
+                    for i in chunk {
+                        buffer.push(1_700_000_000.0 + i as f64 / 2.0, &[i as f32, -(i as f32)]);
+                    }
+                }
+                None => self.cancel_token.cancel(),
+            }
+            Ok(buffer.len())
+        }
+    }
+
+    #[test]
+    fn test_collection_windows_any_source() {
+        let cancel_token = CancellationToken::new();
+        let source = ScriptedSource {
+            chunks: vec![0..3, 3..7, 7..12].into(),
+            cancel_token: cancel_token.clone(),
+        };
+        let (tx, mut rx) = tokio::sync::broadcast::channel(16);
+        let (_pipeline_tx, pipeline_rx) = watch::channel(Pipeline {
+            nodes: vec![Node::Window(WindowConfig {
+                chunk_size: 4,
+                overlap_size: 1,
+            })],
+        });
+        let processing = WindowProcessing {
+            config: ProcessingConfig {
+                n_channels: 2,
+                ..Default::default()
+            },
+            ml: None,
+            bandpass: None,
+            gateway: None,
+            quality: QualityConfig::default(),
+            blinks: None,
+            band_power: None,
+        };
+
+        let (history_tx, mut history_rx) = tokio::sync::mpsc::unbounded_channel();
+        let stats = run_eeg_collection(
+            Box::new(source),
+            tx,
+            cancel_token,
+            processing,
+            pipeline_rx,
+            Some(history_tx),
+        );
+        assert_eq!(stats.samples_received, 12);
+        assert_eq!(stats.packets_sent, 3);
+        assert_eq!(stats.chunks_pulled, 3);
+        assert!(stats.gaps.is_empty());
+        assert_eq!(stats.pipeline_history.len(), 1);
+        assert_eq!(
+            stats.pipeline_history[0].applied_at,
+            unix_secs_to_datetime(1_700_000_000.0)
+        );
+        // the history was handed over while the session ran, and the sender is dropped after it
+        match history_rx.try_recv() {
+            Ok(HistoryEntry::PipelineChange(change)) => {
+                assert_eq!(change.applied_at, stats.pipeline_history[0].applied_at)
+            }
+            other => panic!("expected the initial pipeline, got {:?}", other),
+        }
+        assert!(matches!(
+            history_rx.try_recv(),
+            Err(tokio::sync::mpsc::error::TryRecvError::Disconnected)
+        ));
+
+        // windows are cut across pull boundaries, each repeating the last sample of the previous one
+        let windows: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert_eq!(windows.len(), 3);
+        assert_eq!(windows[0].signals[0], vec![0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(windows[1].signals[0], vec![3.0, 4.0, 5.0, 6.0, 7.0]);
+        assert_eq!(windows[2].signals[1], vec![-7.0, -8.0, -9.0, -10.0, -11.0]);
+        assert_eq!(windows[2].overlap_len, 1);
+        assert_eq!(windows[1].timestamps[0], windows[0].timestamps[3]);
+    }
+
+    #[test]
+    fn test_pipeline_update_applies_window_and_filter_changes() {
+        let initial = Pipeline { nodes: Vec::new() };
+        let (pipeline_tx, mut pipeline_rx) = watch::channel(initial);
+        pipeline_rx.borrow_and_update();
+
+        let mut processing = WindowProcessing {
+            config: ProcessingConfig {
+                n_channels: 8,
+                ..Default::default()
+            },
+            ml: None,
+            bandpass: build_bandpass(&ProcessingConfig::default()).unwrap(),
+            gateway: None,
+            quality: QualityConfig::default(),
+            blinks: None,
+            band_power: None,
+        };
+        let mut windowing = WindowingConfig::default();
+        let mut overlap = Overlap::default();
+
+        // nothing sent yet
+        assert!(take_pipeline_update(
+            &mut pipeline_rx,
+            &mut processing,
+            &mut windowing,
+            &mut overlap
+        )
+        .is_none());
+
+        pipeline_tx.send_replace(Pipeline {
+            nodes: vec![
+                Node::Window(WindowConfig {
+                    chunk_size: 128,
+                    overlap_size: 32,
+                }),
+                Node::Preprocessing(PreprocessingConfig {
+                    apply_bandpass: false,
+                    ..Default::default()
+                }),
+                Node::ML(MLConfig {
+                    model: "focus".to_string(),
+                }),
+            ],
+        });
+        let applied = take_pipeline_update(
+            &mut pipeline_rx,
+            &mut processing,
+            &mut windowing,
+            &mut overlap,
+        );
+        assert_eq!(applied.map(|p| p.nodes.len()), Some(3));
+        assert_eq!(windowing.chunk_size, 128);
+        assert_eq!(windowing.overlap_size, 32);
+        assert!(processing.bandpass.is_none());
+        assert_eq!(processing.config.n_channels, 8);
+        assert_eq!(
+            processing.ml.as_ref().map(|m| m.model.as_str()),
+            Some("focus")
+        );
+
+        // seen updates are not applied twice
+        assert!(take_pipeline_update(
+            &mut pipeline_rx,
+            &mut processing,
+            &mut windowing,
+            &mut overlap
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_window_only_update_restarts_filter_and_overlap() {
+        let (pipeline_tx, mut pipeline_rx) = watch::channel(Pipeline { nodes: Vec::new() });
+        pipeline_rx.borrow_and_update();
+
+        let fresh = build_bandpass(&ProcessingConfig::default()).unwrap();
+        let mut processing = WindowProcessing {
+            config: ProcessingConfig::default(),
+            ml: None,
+            bandpass: fresh.clone(),
+            gateway: None,
+            quality: QualityConfig::default(),
+            blinks: None,
+            band_power: None,
+        };
+        let mut windowing = WindowingConfig::default();
+        let mut overlap = Overlap::default();
+
+        // leave the filter mid-stream and an overlap to carry
+        let mut earlier = vec![(0..256).map(|i| (i as f64 * 0.3).sin() * 50.0).collect()];
+        processing.bandpass.as_mut().unwrap().process(&mut earlier);
+        overlap.signals = earlier.clone();
+        overlap.filtered = earlier;
+        overlap.timestamps = vec![Utc::now(); 256];
+
+        pipeline_tx.send_replace(Pipeline {
+            nodes: vec![Node::Window(WindowConfig {
+                chunk_size: 128,
+                overlap_size: 32,
+            })],
+        });
+        assert!(take_pipeline_update(
+            &mut pipeline_rx,
+            &mut processing,
+            &mut windowing,
+            &mut overlap
+        )
+        .is_some());
+        assert_eq!(windowing.chunk_size, 128);
+        assert!(overlap.signals.is_empty() && overlap.timestamps.is_empty());
+
+        // the next window is filtered as if the stream had just started
+        let next: Vec<Vec<f64>> = vec![(0..128).map(|i| 20.0 + (i as f64 * 0.7).cos()).collect()];
+        let mut expected = next.clone();
+        fresh.unwrap().process(&mut expected);
+        let mut actual = next;
+        processing.bandpass.as_mut().unwrap().process(&mut actual);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_clock_sync_smooths_drift_and_applies_steps() {
+        let now = Utc::now();
+        let mut clock = ClockSync {
+            time_correction: 0.002,
+            ..Default::default()
+        };
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+
+        // the first measurement is applied as is
+        clock.apply(now, 1000.0, false);
+        assert!(close(clock.offset(), 1000.002));
+
+        // small drift is smoothed in
+        clock.apply(now, 1000.012, false);
+        assert!(close(clock.offset(), 1000.0032));
+
+        // a clock reset or a large jump replaces the offset
+        clock.apply(now, 1000.1, true);
+        assert!(close(clock.offset(), 1000.102));
+        clock.apply(now, 2000.0, false);
+        assert!(close(clock.offset(), 2000.002));
+
+        assert_eq!(clock.history.len(), 4);
+        assert!(close(clock.history[1].unix_offset, 1000.012));
+        assert!(close(clock.history[1].applied_offset, 1000.0032));
+    }
+
+    #[test]
+    fn test_stream_selector_predicate() {
+        assert_eq!(
+            StreamSelector::default().to_predicate().unwrap(),
+            "type='EEG'"
+        );
+
+        let selector = StreamSelector {
+            name: Some("Muse-1A2B".to_string()),
+            hostname: Some("lab-pc".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            selector.to_predicate().unwrap(),
+            "type='EEG' and name='Muse-1A2B' and hostname='lab-pc'"
+        );
+
+        let selector = StreamSelector {
+            predicate: Some("starts-with(name,'Muse')".to_string()),
+            source_id: Some("muse-simulator-eeg".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            selector.to_predicate().unwrap(),
+            "(starts-with(name,'Muse')) and source_id='muse-simulator-eeg'"
+        );
+
+        let selector = StreamSelector {
+            name: Some("it's".to_string()),
+            ..Default::default()
+        };
+        assert!(selector.to_predicate().is_err());
+    }
+
+    #[test]
+    fn test_reconnect_backoff() {
+        let delays: Vec<u64> = (0..8).map(|a| reconnect_delay(a).as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 30, 30, 30]);
+    }
+
+    #[test]
+    fn test_stream_selector_timeout() {
+        assert_eq!(
+            StreamSelector::default().resolve_timeout().unwrap(),
+            DEFAULT_RESOLVE_TIMEOUT_SECS
+        );
+        for bad in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let selector = StreamSelector {
+                resolve_timeout_secs: Some(bad),
+                ..Default::default()
+            };
+            assert!(selector.resolve_timeout().is_err());
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use lsl::{resolve_bypred, Pullable, StreamInlet};
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Handle;
+use tokio_util::sync::CancellationToken;
+
+use crate::db::{get_db_client, insert_time_labels};
+use crate::lsl::{unix_secs_to_datetime, ClockSync, StreamSelector};
+use crate::models::NewTimeLabel;
+
+// Color of markers that have no entry in the color mapping
+pub const DEFAULT_MARKER_COLOR: &str = "teal-700";
+// How long a pull waits for a marker, so cancellation is noticed promptly
+const MARKER_PULL_TIMEOUT_SECS: f64 = 0.5;
+
+// An LSL marker stream to record alongside the EEG stream, sent in the WebSocket init message.
+// Each marker becomes a time label; `colors` maps marker text to a label color.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct MarkerConfig {
+    // which stream to record, the first type='Markers' stream when no predicate is given
+    pub stream: StreamSelector,
+    pub colors: HashMap<String, String>,
+    // color of markers missing from `colors`, DEFAULT_MARKER_COLOR when not set
+    pub default_color: Option<String>,
+}
+
+impl MarkerConfig {
+    fn selector(&self) -> StreamSelector {
+        let mut selector = self.stream.clone();
+        if selector
+            .predicate
+            .as_deref()
+            .is_none_or(|p| p.trim().is_empty())
+        {
+            selector.predicate = Some("type='Markers'".to_string());
+        }
+        selector
+    }
+
+    fn color(&self, marker: &str) -> String {
+        self.colors
+            .get(marker)
+            .or(self.default_color.as_ref())
+            .cloned()
+            .unwrap_or_else(|| DEFAULT_MARKER_COLOR.to_string())
+    }
+
+    // A point label for a marker at `time` (Unix time)
+    fn to_label(&self, marker: &str, time: DateTime<Utc>) -> NewTimeLabel {
+        NewTimeLabel {
+            start_timestamp: time,
+            end_timestamp: None,
+            label: marker.to_string(),
+            color: self.color(marker),
+        }
+    }
+}
+
+// Async entry point for marker collection, stored as time labels of the session until
+// cancelled. The marker stream often starts after the EEG (the experiment is launched once
+// recording runs), so it is looked for until it shows up.
+pub async fn receive_markers(
+    cancel_token: CancellationToken,
+    config: MarkerConfig,
+    session_id: i32,
+) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || run_marker_collection(cancel_token, config, session_id))
+        .await
+        .map_err(|e| format!("Marker collection task panicked: {}", e))?
+}
+
+fn run_marker_collection(
+    cancel_token: CancellationToken,
+    config: MarkerConfig,
+    session_id: i32,
+) -> Result<(), String> {
+    let runtime =
+        Handle::try_current().map_err(|e| format!("Markers need the tokio runtime: {}", e))?;
+    let client = get_db_client();
+    let selector = config.selector();
+    // a bad selector won't get better by retrying
+    selector.to_predicate()?;
+    selector.resolve_timeout()?;
+
+    let inlet = loop {
+        if cancel_token.is_cancelled() {
+            return Ok(());
+        }
+        match setup_marker_stream(&selector) {
+            Ok(inlet) => break inlet,
+            Err(e) => warn!("{}, still looking", e),
+        }
+    };
+
+    // markers are mapped to Unix time the same way as the EEG samples
+    let mut clock = ClockSync::default();
+    let mut stored = 0; // for debug purposes
+    while !cancel_token.is_cancelled() {
+        let mut labels = Vec::new();
+        let mut wait = MARKER_PULL_TIMEOUT_SECS;
+        loop {
+            let (sample, timestamp): (Vec<String>, f64) = match inlet.pull_sample(wait) {
+                Ok(pulled) => pulled,
+                Err(e) => {
+                    error!("Failed to pull marker: {}", e);
+                    break;
+                }
+            };
+            if timestamp == 0.0 {
+                break; // no more markers
+            }
+            clock.maybe_sync(&inlet);
+            // marker streams carry the event in their first channel
+            match sample.first().map(|m| m.trim()).filter(|m| !m.is_empty()) {
+                Some(marker) => {
+                    let time = unix_secs_to_datetime(timestamp + clock.offset());
+                    labels.push(config.to_label(marker, time));
+                }
+                None => warn!("Skipping empty marker at {}", timestamp),
+            }
+            wait = 0.0;
+        }
+
+        if labels.is_empty() {
+            continue;
+        }
+        let n_labels = labels.len();
+        match runtime.block_on(insert_time_labels(&client, session_id, labels)) {
+            Ok(()) => stored += n_labels,
+            Err(e) => error!("Failed to store {} marker(s): {}", n_labels, e),
+        }
+    }
+    info!("Stored {} markers for session {}", stored, session_id);
+    Ok(())
+}
+
+fn setup_marker_stream(selector: &StreamSelector) -> Result<StreamInlet, String> {
+    let predicate = selector.to_predicate()?;
+    let timeout = selector.resolve_timeout()?;
+    info!(
+        "Resolving marker stream matching \"{}\" (timeout {}s)",
+        predicate, timeout
+    );
+
+    let streams = resolve_bypred(&predicate, 1, timeout)
+        .map_err(|e| format!("Could not resolve marker stream: {}", e))?;
+    let info = streams.first().ok_or_else(|| {
+        format!(
+            "No marker stream matching \"{}\" found within {}s",
+            predicate, timeout
+        )
+    })?;
+
+    info!(
+        "Marker stream found: {} from {}, creating inlet",
+        info.stream_name(),
+        info.hostname()
+    );
+    // recover = true, so the inlet picks the stream up again if the experiment restarts it
+    StreamInlet::new(info, 360, 0, true)
+        .map_err(|e| format!("Could not create marker StreamInlet: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_marker_config_maps_colors() {
+        let config: MarkerConfig = serde_json::from_str(
+            r#"{"stream": {"name": "PsychoPy"}, "colors": {"stimulus": "teal-300"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.selector().to_predicate().unwrap(),
+            "(type='Markers') and name='PsychoPy'"
+        );
+
+        let time = unix_secs_to_datetime(1_700_000_000.25);
+        let label = config.to_label("stimulus", time);
+        assert_eq!(label.color, "teal-300");
+        assert_eq!(label.start_timestamp.timestamp_subsec_millis(), 250);
+        assert!(label.end_timestamp.is_none());
+        assert_eq!(
+            config.to_label("response", time).color,
+            DEFAULT_MARKER_COLOR
+        );
+    }
+}
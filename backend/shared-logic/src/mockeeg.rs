@@ -1,44 +1,666 @@
 use log::info;
 use lsl::{ChannelFormat, ExPushable, StreamInfo, StreamOutlet};
-use rand::Rng;
-use std::{thread, time::Duration};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::thread;
+use std::time::{Duration, Instant};
 use tokio_util::sync::CancellationToken;
 
+use crate::lsl::StreamSelector;
+
+const DEFAULT_MOCK_SAMPLE_RATE: f64 = 256.0;
+const DEFAULT_MOCK_STREAM_NAME: &str = "MyStream";
+// Samples pushed at once, like the 12-sample packets of a Muse
+const DEFAULT_MOCK_CHUNK_SIZE: usize = 12;
+// Longest the generator sleeps at a time, so cancellation isn't held up
+const MAX_MOCK_SLEEP: Duration = Duration::from_millis(50);
+// Most the generator catches up in one push after falling behind
+const MAX_CATCH_UP_SECS: f64 = 1.0;
+// Rows of the Voss-McCartney pink noise generator, each one an octave lower than the last
+const PINK_NOISE_ROWS: usize = 12;
+const BLINK_SECS: f64 = 0.3;
+const MUSCLE_BURST_SECS: f64 = 0.5;
+
+// A simulated headset, sent in the WebSocket init message as the "mock" source. It is
+// published as an LSL stream and recorded like a real one, so the whole acquisition path runs.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MockConfig {
+    // defaults to the preprocessing node's channel count
+    pub n_channels: Option<usize>,
+    pub sample_rate: f64,
+    // samples per push; the stream's timestamps stay at the exact rate whatever the chunk size
+    pub chunk_size: usize,
+    // name of the LSL stream; sessions running at the same time need different names
+    pub name: String,
+    pub signal: MockSignalConfig,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        Self {
+            n_channels: None,
+            sample_rate: DEFAULT_MOCK_SAMPLE_RATE,
+            chunk_size: DEFAULT_MOCK_CHUNK_SIZE,
+            name: DEFAULT_MOCK_STREAM_NAME.to_string(),
+            signal: MockSignalConfig::default(),
+        }
+    }
+}
+
+impl MockConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.sample_rate.is_finite() && self.sample_rate > 0.0) {
+            return Err(format!(
+                "Mock sample rate must be positive, got {}",
+                self.sample_rate
+            ));
+        }
+        if self.chunk_size == 0 {
+            return Err("Mock chunk size must be at least 1".to_string());
+        }
+        if self.n_channels == Some(0) {
+            return Err("Mock stream needs at least one channel".to_string());
+        }
+        if self.name.trim().is_empty() {
+            return Err("Mock stream needs a name".to_string());
+        }
+        self.selector().to_predicate()?;
+        self.signal.validate()
+    }
+
+    // Selects the mock's own stream, so a real headset on the network isn't picked up instead.
+    pub fn selector(&self) -> StreamSelector {
+        StreamSelector {
+            name: Some(self.name.clone()),
+            source_id: Some(self.source_id()),
+            ..Default::default()
+        }
+    }
+
+    fn source_id(&self) -> String {
+        format!("moss-mock-{}", self.name)
+    }
+}
+
+// What the mock EEG looks like. Amplitudes are in microvolts; every channel gets its own
+// noise, oscillation frequencies and phases, plus the blinks and muscle bursts they share.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MockSignalConfig {
+    // the same seed gives the same signal, a random one is picked when not set
+    pub seed: Option<u64>,
+    // background 1/f noise
+    pub pink_noise: f64,
+    // oscillations around 10 Hz (alpha), 20 Hz (beta) and 6 Hz (theta)
+    pub alpha: f64,
+    pub beta: f64,
+    pub theta: f64,
+    // mains interference, 60 Hz in North America and 50 Hz in most other places
+    pub line_noise: f64,
+    pub line_frequency: f64,
+    pub blinks_per_minute: f64,
+    pub blink_amplitude: f64,
+    pub muscle_bursts_per_minute: f64,
+    pub muscle_amplitude: f64,
+    // segments the signal cycles through, each with its own band amplitudes
+    // (e.g. "relaxed" with strong alpha, then "focused" with strong beta)
+    pub states: Vec<MockState>,
+}
+
+impl Default for MockSignalConfig {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            pink_noise: 10.0,
+            alpha: 10.0,
+            beta: 3.0,
+            theta: 5.0,
+            line_noise: 5.0,
+            line_frequency: 60.0,
+            blinks_per_minute: 15.0,
+            blink_amplitude: 100.0,
+            muscle_bursts_per_minute: 2.0,
+            muscle_amplitude: 20.0,
+            states: Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MockState {
+    pub name: String,
+    pub duration_secs: f64,
+    pub alpha: f64,
+    pub beta: f64,
+    pub theta: f64,
+}
+
+impl MockSignalConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        let amplitudes = [
+            ("pink_noise", self.pink_noise),
+            ("alpha", self.alpha),
+            ("beta", self.beta),
+            ("theta", self.theta),
+            ("line_noise", self.line_noise),
+            ("blinks_per_minute", self.blinks_per_minute),
+            ("blink_amplitude", self.blink_amplitude),
+            ("muscle_bursts_per_minute", self.muscle_bursts_per_minute),
+            ("muscle_amplitude", self.muscle_amplitude),
+        ];
+        for (name, value) in amplitudes {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(format!("{} must be zero or more, got {}", name, value));
+            }
+        }
+        if !(self.line_frequency.is_finite() && self.line_frequency > 0.0) {
+            return Err(format!(
+                "line_frequency must be positive, got {}",
+                self.line_frequency
+            ));
+        }
+        for state in &self.states {
+            if !(state.duration_secs.is_finite() && state.duration_secs > 0.0) {
+                return Err(format!("State {} needs a positive duration", state.name));
+            }
+            if [state.alpha, state.beta, state.theta]
+                .iter()
+                .any(|a| !(a.is_finite() && *a >= 0.0))
+            {
+                return Err(format!("State {} has a negative amplitude", state.name));
+            }
+        }
+        Ok(())
+    }
+
+    // Band amplitudes (alpha, beta, theta) at `t` seconds into the recording
+    fn bands_at(&self, t: f64) -> [f64; 3] {
+        let cycle: f64 = self.states.iter().map(|s| s.duration_secs).sum();
+        if self.states.is_empty() || cycle <= 0.0 {
+            return [self.alpha, self.beta, self.theta];
+        }
+        let mut into_cycle = t % cycle;
+        for state in &self.states {
+            if into_cycle < state.duration_secs {
+                return [state.alpha, state.beta, state.theta];
+            }
+            into_cycle -= state.duration_secs;
+        }
+        let last = &self.states[self.states.len() - 1];
+        [last.alpha, last.beta, last.theta]
+    }
+}
+
+// 1/f noise by the Voss-McCartney algorithm: row k is redrawn every 2^k samples and the
+// output is the sum of all rows plus a fresh white sample. Roughly unit variance.
+struct PinkNoise {
+    rows: [f64; PINK_NOISE_ROWS],
+    counter: u64,
+}
+
+impl PinkNoise {
+    fn new(rng: &mut StdRng) -> Self {
+        Self {
+            rows: std::array::from_fn(|_| gaussian(rng)),
+            counter: 0,
+        }
+    }
+
+    fn next(&mut self, rng: &mut StdRng) -> f64 {
+        self.counter = self.counter.wrapping_add(1);
+        let row = self.counter.trailing_zeros() as usize;
+        if row < PINK_NOISE_ROWS {
+            self.rows[row] = gaussian(rng);
+        }
+        let sum: f64 = self.rows.iter().sum::<f64>() + gaussian(rng);
+        sum / ((PINK_NOISE_ROWS + 1) as f64).sqrt()
+    }
+}
+
+// Per-channel oscillators for alpha, beta and theta
+struct MockChannel {
+    pink: PinkNoise,
+    frequencies: [f64; 3],
+    phases: [f64; 3],
+    // slow waxing and waning of the bands, as real rhythms come and go
+    modulation_frequency: f64,
+    modulation_phase: f64,
+    line_phase: f64,
+}
+
+// A blink or muscle burst, as the sample it started at and how many it lasts
+struct Event {
+    start: u64,
+    len: u64,
+}
+
+impl Event {
+    // Position within the event (0..1), None once it is over
+    fn progress(&self, n: u64) -> Option<f64> {
+        let into = n.checked_sub(self.start)?;
+        (into < self.len).then(|| into as f64 / self.len as f64)
+    }
+}
+
+// Synthesizes mock EEG one sample at a time.
+pub struct MockSignal {
+    config: MockSignalConfig,
+    sample_rate: f64,
+    rng: StdRng,
+    channels: Vec<MockChannel>,
+    n: u64,
+    blink: Option<Event>,
+    muscle: Option<Event>,
+}
+
+impl MockSignal {
+    pub fn new(config: MockSignalConfig, n_channels: usize, sample_rate: f64) -> Self {
+        let mut rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let channels = (0..n_channels)
+            .map(|_| MockChannel {
+                pink: PinkNoise::new(&mut rng),
+                frequencies: [
+                    rng.gen_range(9.0..11.0),
+                    rng.gen_range(18.0..22.0),
+                    rng.gen_range(5.0..7.0),
+                ],
+                phases: std::array::from_fn(|_| rng.gen_range(0.0..2.0 * PI)),
+                modulation_frequency: rng.gen_range(0.05..0.2),
+                modulation_phase: rng.gen_range(0.0..2.0 * PI),
+                line_phase: rng.gen_range(0.0..2.0 * PI),
+            })
+            .collect();
+        Self {
+            config,
+            sample_rate,
+            rng,
+            channels,
+            n: 0,
+            blink: None,
+            muscle: None,
+        }
+    }
+
+    pub fn next_sample(&mut self) -> Vec<f32> {
+        let t = self.n as f64 / self.sample_rate;
+        let bands = self.config.bands_at(t);
+        self.start_events();
+
+        // blinks are a slow positive bump, strongest on the first (frontal) channels
+        let blink = self
+            .blink
+            .as_ref()
+            .and_then(|e| e.progress(self.n))
+            .map(|x| self.config.blink_amplitude * (PI * x).sin().powi(2))
+            .unwrap_or(0.0);
+        // muscle bursts are broadband noise under a smooth envelope
+        let muscle = self
+            .muscle
+            .as_ref()
+            .and_then(|e| e.progress(self.n))
+            .map(|x| self.config.muscle_amplitude * (PI * x).sin())
+            .unwrap_or(0.0);
+
+        let n_channels = self.channels.len();
+        let mut sample = Vec::with_capacity(n_channels);
+        for (ch, channel) in self.channels.iter_mut().enumerate() {
+            let modulation = 1.0
+                + 0.3
+                    * (2.0 * PI * channel.modulation_frequency * t + channel.modulation_phase)
+                        .sin();
+            let rhythms: f64 = (0..3)
+                .map(|b| {
+                    bands[b] * (2.0 * PI * channel.frequencies[b] * t + channel.phases[b]).sin()
+                })
+                .sum();
+            let line = self.config.line_noise
+                * (2.0 * PI * self.config.line_frequency * t + channel.line_phase).sin();
+            let blink_weight = 1.0 - ch as f64 / n_channels.max(1) as f64 * 0.75;
+
+            let mut value = self.config.pink_noise * channel.pink.next(&mut self.rng)
+                + modulation * rhythms
+                + line
+                + blink_weight * blink;
+            if muscle > 0.0 {
+                value += muscle * gaussian(&mut self.rng);
+            }
+            sample.push(value as f32);
+        }
+        self.n += 1;
+        sample
+    }
+
+    // Starts blinks and muscle bursts at random, as Poisson processes at their per-minute rates
+    fn start_events(&mut self) {
+        let n = self.n;
+        let per_sample = 1.0 / (60.0 * self.sample_rate);
+        if self.blink.as_ref().and_then(|e| e.progress(n)).is_none()
+            && self.rng.gen::<f64>() < self.config.blinks_per_minute * per_sample
+        {
+            self.blink = Some(Event {
+                start: n,
+                len: (BLINK_SECS * self.sample_rate).max(1.0) as u64,
+            });
+        }
+        if self.muscle.as_ref().and_then(|e| e.progress(n)).is_none()
+            && self.rng.gen::<f64>() < self.config.muscle_bursts_per_minute * per_sample
+        {
+            let secs = MUSCLE_BURST_SECS * self.rng.gen_range(0.5..2.0);
+            self.muscle = Some(Event {
+                start: n,
+                len: (secs * self.sample_rate).max(1.0) as u64,
+            });
+        }
+    }
+}
+
+// Standard normal sample (Box-Muller)
+fn gaussian(rng: &mut StdRng) -> f64 {
+    let u1: f64 = rng.gen_range(f64::EPSILON..1.0);
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
+
+// How closely the mock kept to its nominal rate, reported when it stops.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct MockTimingStats {
+    pub samples_sent: u64,
+    pub elapsed_secs: f64,
+    pub nominal_rate: f64,
+    pub achieved_rate: f64,
+    // pushes that had to catch up more than one chunk because the generator woke up late
+    pub late_pushes: u64,
+}
+
+// Sample n is due `n / rate` seconds after the start, however late earlier samples went out,
+// so scheduler jitter delays pushes but never adds up to drift.
+struct MockSchedule {
+    started: Instant,
+    sample_rate: f64,
+    chunk_size: u64,
+    // most samples pushed at once after a late wake-up
+    max_catch_up: u64,
+}
+
+// What the generator does next
+#[derive(Debug, PartialEq)]
+enum ScheduleStep {
+    // nothing to push before this instant
+    SleepUntil(Instant),
+    // push this many samples (whole chunks); late if more than one chunk was due
+    Push { samples: u64, late: bool },
+}
+
+impl MockSchedule {
+    // Number of samples due by `now`
+    fn due_by(&self, now: Instant) -> u64 {
+        (now.saturating_duration_since(self.started).as_secs_f64() * self.sample_rate) as u64
+    }
+
+    fn due_at(&self, n: u64) -> Instant {
+        self.started + Duration::from_secs_f64(n as f64 / self.sample_rate)
+    }
+
+    // The step to take at `now` once `sent` samples have gone out.
+    fn next_step(&self, now: Instant, sent: u64) -> ScheduleStep {
+        let due = self.due_by(now).saturating_sub(sent);
+        if due < self.chunk_size {
+            return ScheduleStep::SleepUntil(self.due_at(sent + self.chunk_size));
+        }
+        ScheduleStep::Push {
+            samples: (due.min(self.max_catch_up) / self.chunk_size) * self.chunk_size,
+            late: due >= 2 * self.chunk_size,
+        }
+    }
+}
+
+// Publishes the mock EEG as an LSL stream until cancelled. `n_channels` is used when the
+// config doesn't set a channel count. Runs on a blocking thread, not a runtime worker.
 pub async fn generate_mock_data(
     cancel_token: CancellationToken,
-) -> Result<(), Box<dyn std::error::Error>> {
+    config: MockConfig,
+    n_channels: usize,
+) -> Result<MockTimingStats, String> {
+    tokio::task::spawn_blocking(move || run_mock_generator(cancel_token, config, n_channels))
+        .await
+        .map_err(|e| format!("Mock generator panicked: {}", e))?
+}
+
+fn run_mock_generator(
+    cancel_token: CancellationToken,
+    config: MockConfig,
+    n_channels: usize,
+) -> Result<MockTimingStats, String> {
+    config.validate()?;
+    let n_channels = config.n_channels.unwrap_or(n_channels);
+    let source_id = config.source_id();
+
     // Create stream info
     let stream_info = StreamInfo::new(
-        "MyStream",             // stream name
+        &config.name,           // stream name
         "EEG",                  // content type (EEG, EMG, etc.)
-        4,                      // number of channels
-        256.0,                  // sampling rate (Hz)
+        n_channels as u32,      // number of channels
+        config.sample_rate,     // sampling rate (Hz)
         ChannelFormat::Float32, // data format
-        "muse-simulator-eeg",   // source ID (should be unique)
-    )?;
+        &source_id,             // source ID (should be unique)
+    )
+    .map_err(|e| e.to_string())?;
 
     // Create outlet
-    let outlet = StreamOutlet::new(&stream_info, 0, 360)?; // 0 = default chunk size, 360 = max buffered
+    let outlet = StreamOutlet::new(&stream_info, config.chunk_size as i32, 360) // 360 = max buffered
+        .map_err(|e| e.to_string())?;
+    info!(
+        "Mock stream {} created, sending {} channels at {} Hz",
+        config.name, n_channels, config.sample_rate
+    );
+
+    let signal = MockSignal::new(config.signal.clone(), n_channels, config.sample_rate);
+    let stats = run_mock_schedule(&cancel_token, &config, signal, |samples, stamps| {
+        outlet
+            .push_chunk_stamped_ex(samples, stamps, true)
+            .map_err(|e| e.to_string())
+    })?;
+    info!(
+        "Mock stream {} sent {} samples in {:.1}s: {:.2} Hz for a nominal {} Hz ({} late pushes)",
+        config.name,
+        stats.samples_sent,
+        stats.elapsed_secs,
+        stats.achieved_rate,
+        stats.nominal_rate,
+        stats.late_pushes
+    );
+    Ok(stats)
+}
 
-    println!("Stream created. Sending data...");
+// Hands the signal to `push` in chunks of chunk_size samples as they fall due, stamped with
+// the LSL time they were due at. A late wake-up pushes every whole chunk due so far.
+fn run_mock_schedule(
+    cancel_token: &CancellationToken,
+    config: &MockConfig,
+    mut signal: MockSignal,
+    mut push: impl FnMut(&Vec<Vec<f32>>, &Vec<f64>) -> Result<(), String>,
+) -> Result<MockTimingStats, String> {
+    let chunk_size = config.chunk_size as u64;
+    let lsl_start = lsl::local_clock();
+    let schedule = MockSchedule {
+        started: Instant::now(),
+        sample_rate: config.sample_rate,
+        chunk_size,
+        max_catch_up: ((MAX_CATCH_UP_SECS * config.sample_rate) as u64).max(chunk_size),
+    };
+    let mut stats = MockTimingStats {
+        nominal_rate: config.sample_rate,
+        ..Default::default()
+    };
+    let mut samples = Vec::new();
+    let mut stamps = Vec::new();
 
-    // Send data in a loop
-    loop {
-        if cancel_token.is_cancelled() {
-            info!("Cancellation requested, stopping data generation...");
-            break;
+    while !cancel_token.is_cancelled() {
+        let now = Instant::now();
+        let n = match schedule.next_step(now, stats.samples_sent) {
+            ScheduleStep::SleepUntil(wake) => {
+                thread::sleep(wake.saturating_duration_since(now).min(MAX_MOCK_SLEEP));
+                continue;
+            }
+            ScheduleStep::Push { samples, late } => {
+                stats.late_pushes += late as u64;
+                samples
+            }
+        };
+
+        samples.clear();
+        stamps.clear();
+        for i in stats.samples_sent..stats.samples_sent + n {
+            samples.push(signal.next_sample());
+            stamps.push(lsl_start + i as f64 / config.sample_rate);
         }
+        push(&samples, &stamps)?;
+        stats.samples_sent += n;
+    }
 
-        // Create sample data (4 channels of float data)
-        let sample_data: Vec<f32> = (0..4)
-            .map(|_| rand::thread_rng().gen_range(0.0..100.0))
-            .collect();
+    stats.elapsed_secs = schedule.started.elapsed().as_secs_f64();
+    if stats.elapsed_secs > 0.0 {
+        stats.achieved_rate = stats.samples_sent as f64 / stats.elapsed_secs;
+    }
+    info!("Cancellation requested, stopping data generation...");
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Amplitude of the `freq` component of a signal (single DFT bin)
+    fn amplitude_at(signal: &[f64], freq: f64, sample_rate: f64) -> f64 {
+        let (re, im) = signal
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (i, v)| {
+                let angle = 2.0 * PI * freq * i as f64 / sample_rate;
+                (re + v * angle.cos(), im - v * angle.sin())
+            });
+        2.0 * (re * re + im * im).sqrt() / signal.len() as f64
+    }
+
+    #[test]
+    fn test_mock_signal_is_seeded_and_follows_states() {
+        let config = MockSignalConfig {
+            seed: Some(42),
+            blinks_per_minute: 0.0,
+            muscle_bursts_per_minute: 0.0,
+            states: vec![
+                MockState {
+                    name: "relaxed".to_string(),
+                    duration_secs: 4.0,
+                    alpha: 30.0,
+                    beta: 2.0,
+                    theta: 5.0,
+                },
+                MockState {
+                    name: "focused".to_string(),
+                    duration_secs: 4.0,
+                    alpha: 2.0,
+                    beta: 30.0,
+                    theta: 5.0,
+                },
+            ],
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        let mut a = MockSignal::new(config.clone(), 2, DEFAULT_MOCK_SAMPLE_RATE);
+        let mut b = MockSignal::new(config, 2, DEFAULT_MOCK_SAMPLE_RATE);
+        let samples: Vec<Vec<f32>> = (0..8 * 256).map(|_| a.next_sample()).collect();
+        assert!(samples.iter().all(|s| *s == b.next_sample()));
 
-        // Send the sample
-        outlet.push_sample_ex(&sample_data, lsl::local_clock(), true)?;
-        // Sleep to simulate real-time data
-        thread::sleep(Duration::from_millis(4));
+        // the 60 Hz line noise is there throughout
+        let channel: Vec<f64> = samples.iter().map(|s| s[0] as f64).collect();
+        let (relaxed, focused) = channel.split_at(4 * 256);
+        assert!((amplitude_at(relaxed, 60.0, DEFAULT_MOCK_SAMPLE_RATE) - 5.0).abs() < 1.5);
+
+        // alpha dominates the relaxed segment, beta the focused one
+        let band = |segment: &[f64], lo: f64, hi: f64| {
+            let mut freq = lo;
+            let mut peak: f64 = 0.0;
+            while freq <= hi {
+                peak = peak.max(amplitude_at(segment, freq, DEFAULT_MOCK_SAMPLE_RATE));
+                freq += 0.25;
+            }
+            peak
+        };
+        assert!(band(relaxed, 9.0, 11.0) > 3.0 * band(relaxed, 18.0, 22.0));
+        assert!(band(focused, 18.0, 22.0) > 3.0 * band(focused, 9.0, 11.0));
+    }
+
+    #[test]
+    fn test_mock_schedule_keeps_the_nominal_rate() {
+        // driven by a simulated clock, so the test doesn't depend on how the machine schedules
+        let start = Instant::now();
+        let schedule = MockSchedule {
+            started: start,
+            sample_rate: 500.0,
+            chunk_size: 10,
+            max_catch_up: 100,
+        };
+        let at = |ms: f64| start + Duration::from_secs_f64(ms / 1000.0);
+
+        // a chunk is due every 20 ms, and nothing before it
+        assert_eq!(
+            schedule.next_step(at(0.0), 0),
+            ScheduleStep::SleepUntil(at(20.0))
+        );
+        assert_eq!(
+            schedule.next_step(at(20.0), 0),
+            ScheduleStep::Push {
+                samples: 10,
+                late: false
+            }
+        );
+        // waking 60 ms late catches up on the three whole chunks due, and is counted as late
+        assert_eq!(
+            schedule.next_step(at(100.0), 20),
+            ScheduleStep::Push {
+                samples: 30,
+                late: true
+            }
+        );
+        // a long stall catches up at most max_catch_up samples at a time
+        assert_eq!(
+            schedule.next_step(at(5_000.0), 0),
+            ScheduleStep::Push {
+                samples: 100,
+                late: true
+            }
+        );
+
+        // run 10 s with wake-ups up to 35 ms late: no samples are lost and the rate doesn't
+        // drift, pushes fall behind by at most the lateness (17.5 samples) plus one chunk
+        let mut now = 0.0;
+        let mut sent = 0;
+        let mut late_pushes = 0;
+        let mut wakes = 0;
+        while now < 10_000.0 {
+            match schedule.next_step(at(now), sent) {
+                ScheduleStep::SleepUntil(until) => {
+                    wakes += 1;
+                    now = (until - start).as_secs_f64() * 1000.0 + (wakes % 8) as f64 * 5.0;
+                }
+                ScheduleStep::Push { samples, late } => {
+                    assert_eq!(samples % 10, 0);
+                    sent += samples;
+                    late_pushes += late as u64;
+                }
+            }
+            let behind = schedule.due_by(at(now)) - sent;
+            assert!(behind < 28, "{} samples behind at {} ms", behind, now);
+        }
+        assert!(late_pushes >= 1);
+        assert!(sent >= 5_000 - 28, "sent {} samples in 10 s", sent);
     }
-    drop(outlet);
-    Ok(())
 }
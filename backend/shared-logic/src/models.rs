@@ -3,6 +3,8 @@ use serde::{Deserialize, Serialize};
 
 use serde_json::Value;
 
+use crate::signal_processing::spectrum::{WelchConfig, WindowFunction};
+
 // Existing User struct (used for data coming OUT of the DB)
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, sqlx::FromRow)]
 pub struct User {
@@ -74,13 +76,37 @@ pub struct NewTimeLabel {
 }
 
 // Struct for a row of EEG data coming OUT of the DB
+// channels holds one value per headset channel, in channel order (microvolts, as recorded)
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct EegDataRow {
     pub time: DateTime<Utc>,
-    pub channel1: i32,
-    pub channel2: i32,
-    pub channel3: i32,
-    pub channel4: i32,
+    pub channels: Vec<f64>,
+}
+
+// Struct for one clock synchronization step of a recording session.
+// EEG timestamps are stored as remote LSL time + applied_offset (seconds), where
+// applied_offset is the smoothed time_correction (remote -> local LSL clock)
+// plus unix_offset (local LSL clock -> Unix time).
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ClockOffset {
+    pub measured_at: DateTime<Utc>,
+    pub time_correction: f64,
+    pub unix_offset: f64,
+    pub applied_offset: f64,
+}
+
+// Struct for a stretch of a recording session where the EEG stream was lost
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct StreamGap {
+    pub gap_start: DateTime<Utc>,
+    pub gap_end: DateTime<Utc>,
+}
+
+// Struct for a pipeline a recording session ran with, from applied_at until the next change
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PipelineChange {
+    pub applied_at: DateTime<Utc>,
+    pub pipeline: Value,
 }
 
 // Struct for the query parameters on GET /api/sessions/{session_id}/eeg-data
@@ -89,3 +115,43 @@ pub struct EegDataQuery {
     pub start: DateTime<Utc>,
     pub end: DateTime<Utc>,
 }
+
+// Struct for the query parameters on GET /api/sessions/{session_id}/psd
+#[derive(Debug, Deserialize)]
+pub struct PsdQuery {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    // comma separated channel indices, all channels when not given
+    pub channels: Option<String>,
+    // Welch settings, see WelchConfig
+    pub segment_len: Option<usize>,
+    pub overlap: Option<usize>,
+    #[serde(default)]
+    pub window: WindowFunction,
+}
+
+impl PsdQuery {
+    pub fn welch_config(&self) -> WelchConfig {
+        WelchConfig {
+            segment_len: self.segment_len,
+            overlap: self.overlap,
+            window: self.window,
+        }
+    }
+
+    // The requested channel indices, None for all of them
+    pub fn channel_indices(&self) -> Result<Option<Vec<usize>>, String> {
+        let Some(channels) = self.channels.as_deref().filter(|c| !c.trim().is_empty()) else {
+            return Ok(None);
+        };
+        channels
+            .split(',')
+            .map(|c| {
+                c.trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid channel index: {}", c))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some)
+    }
+}
@@ -0,0 +1,208 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use lsl::{ChannelFormat, ExPushable, StreamInfo, StreamOutlet};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+
+use crate::formats::estimate_sample_rate;
+use crate::lsl::{datetime_to_unix_secs, EEGDataPacket};
+use crate::signal_processing::pipeline_gateway::PipelineOutput;
+
+const DEFAULT_OUTLET_NAME: &str = "moss";
+// Seconds of data the outlets buffer for consumers that fall behind
+const OUTLET_MAX_BUFFERED_SECS: i32 = 360;
+
+// Which LSL outlets a session republishes its processed windows on, so LSL tools on the
+// network (stimulus software, LabRecorder) can subscribe to them. Both are off by default.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct OutletConfig {
+    // the signal after the pipeline (filtered), as "<name>-filtered" with type EEG
+    pub signal: bool,
+    // the classifier results as "<name>-ml", a string marker stream of label, confidence, task
+    pub ml: bool,
+    // prefix of the outlet names, "moss" when not set
+    pub name: Option<String>,
+}
+
+impl OutletConfig {
+    pub fn enabled(&self) -> bool {
+        self.signal || self.ml
+    }
+
+    fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(DEFAULT_OUTLET_NAME)
+    }
+}
+
+// outlet_receiver takes EEGDataPacket structs from the broadcast sender and pushes them to the
+// configured LSL outlets. LSL outlets are blocking, so this runs in spawn_blocking.
+pub fn outlet_receiver(mut rx: Receiver<Arc<EEGDataPacket>>, config: OutletConfig) {
+    let name = config.name();
+    // the signal outlet needs the channel count and rate, so it opens with the first packet
+    let mut signal_outlet: Option<(StreamOutlet, usize)> = None;
+    let ml_outlet = if config.ml {
+        match open_ml_outlet(name) {
+            Ok(outlet) => Some(outlet),
+            Err(e) => {
+                error!("Failed to open the ML outlet: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut pushed = 0; // for debug purposes
+    loop {
+        let packet = match rx.blocking_recv() {
+            Ok(packet) => packet,
+            Err(RecvError::Lagged(n)) => {
+                error!("Outlet receiver lagged, missed {} messages", n);
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+        // EEG timestamps are Unix time, LSL consumers expect local_clock time
+        let unix_to_lsl = lsl::local_clock() - datetime_to_unix_secs(Utc::now());
+
+        if config.signal {
+            let (samples, stamps) = new_samples(&packet, unix_to_lsl);
+            let n_channels = packet.processed_signals().len();
+            if signal_outlet.is_none() && !samples.is_empty() {
+                match open_signal_outlet(name, n_channels, &packet.timestamps) {
+                    Ok(outlet) => signal_outlet = Some((outlet, n_channels)),
+                    Err(e) => error!("Failed to open the signal outlet: {}", e),
+                }
+            }
+            match &signal_outlet {
+                Some((outlet, channels)) if *channels == n_channels && !samples.is_empty() => {
+                    if let Err(e) = outlet.push_chunk_stamped_ex(&samples, &stamps, true) {
+                        error!("Failed to push to the signal outlet: {}", e);
+                    }
+                }
+                Some((_, channels)) if *channels != n_channels => error!(
+                    "Packet has {} channels but the signal outlet has {}, skipped",
+                    n_channels, channels
+                ),
+                _ => {}
+            }
+        }
+
+        if let (Some(outlet), Some(result)) = (&ml_outlet, &packet.ml_result) {
+            // the result describes the whole window, it is stamped with its last sample
+            let stamp = packet
+                .timestamps
+                .last()
+                .map(|&time| datetime_to_unix_secs(time) + unix_to_lsl)
+                .unwrap_or_else(lsl::local_clock);
+            if let Err(e) = outlet.push_sample_ex(&ml_sample(result), stamp, true) {
+                error!("Failed to push to the ML outlet: {}", e);
+            }
+        }
+        pushed += 1;
+    }
+    info!("Outlets got {} packets", pushed);
+}
+
+fn open_signal_outlet(
+    name: &str,
+    n_channels: usize,
+    timestamps: &[DateTime<Utc>],
+) -> Result<StreamOutlet, String> {
+    let rate = estimate_sample_rate(timestamps)
+        .map(f64::round)
+        .unwrap_or(lsl::IRREGULAR_RATE);
+    let outlet_name = format!("{}-filtered", name);
+    let info = StreamInfo::new(
+        &outlet_name,
+        "EEG",
+        n_channels as u32,
+        rate,
+        ChannelFormat::Double64,
+        &outlet_name,
+    )
+    .map_err(|e| e.to_string())?;
+    let outlet =
+        StreamOutlet::new(&info, 0, OUTLET_MAX_BUFFERED_SECS).map_err(|e| e.to_string())?;
+    info!(
+        "Opened LSL outlet {} ({} channels at {} Hz)",
+        outlet_name, n_channels, rate
+    );
+    Ok(outlet)
+}
+
+fn open_ml_outlet(name: &str) -> Result<StreamOutlet, String> {
+    let outlet_name = format!("{}-ml", name);
+    let info = StreamInfo::new(
+        &outlet_name,
+        "Markers",
+        3,
+        lsl::IRREGULAR_RATE,
+        ChannelFormat::String,
+        &outlet_name,
+    )
+    .map_err(|e| e.to_string())?;
+    let outlet =
+        StreamOutlet::new(&info, 0, OUTLET_MAX_BUFFERED_SECS).map_err(|e| e.to_string())?;
+    info!("Opened LSL outlet {}", outlet_name);
+    Ok(outlet)
+}
+
+// The samples of a packet that weren't in the previous window (one Vec per sample, channel
+// after channel), with their LSL timestamps. Filtered samples are stamped with the time they
+// were measured, so consumers see them aligned with the raw stream.
+fn new_samples(packet: &EEGDataPacket, unix_to_lsl: f64) -> (Vec<Vec<f64>>, Vec<f64>) {
+    let start = packet.overlap_len.min(packet.timestamps.len());
+    (start..packet.timestamps.len())
+        .map(|i| {
+            let sample = packet
+                .processed_signals()
+                .iter()
+                .map(|channel| channel.get(i).copied().unwrap_or(0.0))
+                .collect();
+            (
+                sample,
+                datetime_to_unix_secs(packet.timestamps[i]) - packet.filter_delay_secs
+                    + unix_to_lsl,
+            )
+        })
+        .unzip()
+}
+
+fn ml_sample(result: &PipelineOutput) -> Vec<String> {
+    vec![
+        result.overall_label.clone(),
+        result.confidence.to_string(),
+        result.task.clone(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_samples_skip_overlap() {
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let packet = EEGDataPacket {
+            timestamps: (0..4)
+                .map(|i| start + chrono::Duration::milliseconds(250 * i))
+                .collect(),
+            signals: vec![vec![1.0, 2.0, 3.0, 4.0], vec![-1.0, -2.0, -3.0, -4.0]],
+            overlap_len: 2,
+            ..Default::default()
+        };
+
+        let (samples, stamps) = new_samples(&packet, -1_699_999_000.0);
+        assert_eq!(samples, vec![vec![3.0, -3.0], vec![4.0, -4.0]]);
+        assert_eq!(stamps, vec![1000.5, 1000.75]);
+
+        let config: OutletConfig = serde_json::from_str(r#"{"ml": true}"#).unwrap();
+        assert!(config.enabled() && !config.signal);
+        assert_eq!(config.name(), "moss");
+    }
+}
@@ -1,5 +1,10 @@
 use serde::{Deserialize, Serialize};
 
+use crate::signal_processing::blinks::BlinkConfig;
+use crate::signal_processing::filters::BandpassFilter;
+use crate::signal_processing::quality::QualityConfig;
+use crate::signal_processing::spectrum::BandPowerConfig;
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Pipeline {
     pub nodes: Vec<Node>,
@@ -16,14 +21,43 @@ pub enum Node {
 
     #[serde(rename = "ml")]
     ML(MLConfig),
+
+    // thresholds of the per-channel contact quality, which is rated with defaults without it
+    #[serde(rename = "signalQuality")]
+    SignalQuality(QualityConfig),
+
+    // eye blink detection on the frontal channels, off without it
+    #[serde(rename = "blinkDetection")]
+    BlinkDetection(BlinkConfig),
+
+    // per-channel power in each frequency band of the filtered window, off without it
+    #[serde(rename = "bandPower")]
+    BandPower(BandPowerConfig),
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct WindowConfig {
     pub chunk_size: usize,
     pub overlap_size: usize,
 }
 
+impl WindowConfig {
+    // Checks the config can produce windows: chunk_size new samples per window,
+    // plus overlap_size samples repeated from the previous one.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.chunk_size == 0 {
+            return Err("chunk_size must be greater than 0".to_string());
+        }
+        if self.overlap_size >= self.chunk_size {
+            return Err(format!(
+                "overlap_size ({}) must be smaller than chunk_size ({})",
+                self.overlap_size, self.chunk_size
+            ));
+        }
+        Ok(())
+    }
+}
+
 impl Default for WindowConfig {
     fn default() -> Self {
         Self {
@@ -34,7 +68,7 @@ impl Default for WindowConfig {
 }
 
 // Fields moved from ProcessingConfig in lsl.rs
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct PreprocessingConfig {
     pub apply_bandpass: bool,
     pub use_iir: bool,
@@ -90,4 +124,110 @@ impl Pipeline {
             .iter()
             .find_map(|n| if let Node::ML(c) = n { Some(c) } else { None })
     }
+
+    pub fn quality_config(&self) -> Option<&QualityConfig> {
+        self.nodes.iter().find_map(|n| {
+            if let Node::SignalQuality(c) = n {
+                Some(c)
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn blink_config(&self) -> Option<&BlinkConfig> {
+        self.nodes.iter().find_map(|n| {
+            if let Node::BlinkDetection(c) = n {
+                Some(c)
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn band_power_config(&self) -> Option<&BandPowerConfig> {
+        self.nodes.iter().find_map(|n| {
+            if let Node::BandPower(c) = n {
+                Some(c)
+            } else {
+                None
+            }
+        })
+    }
+
+    // Replaces the node of the same type, or adds it if the pipeline doesn't have one.
+    pub fn set_node(&mut self, node: Node) {
+        let kind = std::mem::discriminant(&node);
+        match self
+            .nodes
+            .iter_mut()
+            .find(|n| std::mem::discriminant(*n) == kind)
+        {
+            Some(existing) => *existing = node,
+            None => self.nodes.push(node),
+        }
+    }
+
+    // Checks the nodes can be run, so a bad update is rejected before it reaches the session.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(window) = self.window_config() {
+            window.validate()?;
+        }
+        if let Some(preprocessing) = self.preprocessing_config() {
+            if preprocessing.apply_bandpass {
+                BandpassFilter::from_config(preprocessing)?;
+            }
+        }
+        if let Some(quality) = self.quality_config() {
+            quality.validate()?;
+        }
+        if let Some(blinks) = self.blink_config() {
+            blinks.validate()?;
+        }
+        if let Some(band_power) = self.band_power_config() {
+            band_power.validate()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_config_validation() {
+        let config = |chunk_size, overlap_size| WindowConfig {
+            chunk_size,
+            overlap_size,
+        };
+        assert!(config(64, 0).validate().is_ok());
+        assert!(config(64, 63).validate().is_ok());
+        assert!(config(64, 64).validate().is_err());
+        assert!(config(0, 0).validate().is_err());
+    }
+
+    #[test]
+    fn test_set_node_replaces_same_type() {
+        let mut pipeline = Pipeline {
+            nodes: vec![Node::Window(WindowConfig::default())],
+        };
+        pipeline.set_node(Node::Window(WindowConfig {
+            chunk_size: 128,
+            overlap_size: 32,
+        }));
+        pipeline.set_node(Node::ML(MLConfig {
+            model: "focus".to_string(),
+        }));
+
+        assert_eq!(pipeline.nodes.len(), 2);
+        assert_eq!(pipeline.window_config().map(|w| w.chunk_size), Some(128));
+        assert!(pipeline.validate().is_ok());
+
+        pipeline.set_node(Node::Window(WindowConfig {
+            chunk_size: 16,
+            overlap_size: 16,
+        }));
+        assert!(pipeline.validate().is_err());
+    }
 }
@@ -0,0 +1,247 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Handle;
+
+use crate::db::{
+    get_db_client, get_earliest_eeg_timestamp, get_eeg_data_by_range, get_latest_eeg_timestamp,
+    DbClient,
+};
+use crate::lsl::datetime_to_unix_secs;
+use crate::models::EegDataRow;
+use crate::source::{EegSource, PullBuffer};
+
+// How much of the recording is read from the database at a time
+const REPLAY_PAGE_SECS: i64 = 10;
+
+// Which stored session to replay, sent in the WebSocket init message as the "replay" source.
+// Without start/end the whole session is replayed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReplayConfig {
+    pub session_id: i32,
+    #[serde(default)]
+    pub start: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub end: Option<DateTime<Utc>>,
+    // 2.0 plays the recording twice as fast as it was recorded
+    #[serde(default = "default_speed")]
+    pub speed: f64,
+}
+
+fn default_speed() -> f64 {
+    1.0
+}
+
+impl ReplayConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.speed.is_finite() && self.speed > 0.0) {
+            return Err(format!("speed must be positive, got {}", self.speed));
+        }
+        if let (Some(start), Some(end)) = (self.start, self.end) {
+            if end < start {
+                return Err(format!("end ({}) is before start ({})", end, start));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Plays the samples of a stored session back at their recorded pace (times `speed`).
+// Rows are read from eeg_data a page at a time, so long sessions aren't loaded all at once.
+// Timestamps are the recorded ones, already Unix time.
+pub struct ReplaySource {
+    client: DbClient,
+    runtime: Handle,
+    session_id: i32,
+    end: DateTime<Utc>,
+    speed: f64,
+    n_channels: usize,
+    // rows read but not replayed yet
+    pending: VecDeque<EegDataRow>,
+    // everything up to here has been read
+    read_until: DateTime<Utc>,
+    // the first row is replayed at `started`, later rows relative to it
+    first_time: DateTime<Utc>,
+    started: Instant,
+}
+
+impl ReplaySource {
+    // Finds the range to replay and reads its first rows. Must be called from a blocking
+    // thread of the runtime (the collection loop runs in spawn_blocking).
+    pub fn connect(config: ReplayConfig) -> Result<Self, String> {
+        config.validate()?;
+        let runtime =
+            Handle::try_current().map_err(|e| format!("Replay needs the tokio runtime: {}", e))?;
+        let client = get_db_client();
+
+        let start = match config.start {
+            Some(start) => start,
+            None => runtime
+                .block_on(get_earliest_eeg_timestamp(&client, config.session_id))
+                .map_err(|e| format!("Failed to read session {}: {}", config.session_id, e))?
+                .ok_or_else(|| format!("Session {} has no EEG data", config.session_id))?,
+        };
+        let end = match config.end {
+            Some(end) => end,
+            None => runtime
+                .block_on(get_latest_eeg_timestamp(&client, config.session_id))
+                .map_err(|e| format!("Failed to read session {}: {}", config.session_id, e))?
+                .unwrap_or(start),
+        };
+
+        let mut source = Self {
+            client,
+            runtime,
+            session_id: config.session_id,
+            end,
+            speed: config.speed,
+            n_channels: 0,
+            pending: VecDeque::new(),
+            // rows are read after read_until, so the first page includes start itself
+            read_until: start - chrono::Duration::nanoseconds(1),
+            first_time: start,
+            started: Instant::now(),
+        };
+        while source.pending.is_empty() && source.read_until < source.end {
+            source.read_page()?;
+        }
+        let first = source.pending.front().ok_or_else(|| {
+            format!(
+                "Session {} has no EEG data between {} and {}",
+                config.session_id, start, end
+            )
+        })?;
+
+        source.n_channels = first.channels.len();
+        source.first_time = first.time;
+        source.started = Instant::now();
+        info!(
+            "Replaying session {} from {} to {} at {}x ({} channels)",
+            source.session_id, source.first_time, source.end, source.speed, source.n_channels
+        );
+        Ok(source)
+    }
+
+    // Reads the next REPLAY_PAGE_SECS of the recording.
+    fn read_page(&mut self) -> Result<(), String> {
+        let page_end =
+            (self.read_until + chrono::Duration::seconds(REPLAY_PAGE_SECS)).min(self.end);
+        let rows = self
+            .runtime
+            .block_on(get_eeg_data_by_range(
+                &self.client,
+                self.session_id,
+                self.read_until,
+                page_end,
+            ))
+            .map_err(|e| format!("Failed to read session {}: {}", self.session_id, e))?;
+
+        // the range is inclusive, the row at read_until was in the previous page
+        let read_until = self.read_until;
+        self.pending
+            .extend(rows.into_iter().filter(|row| row.time > read_until));
+        self.read_until = page_end;
+        Ok(())
+    }
+
+    // When a row of the recording is due to be replayed
+    fn due_at(&self, time: DateTime<Utc>) -> Instant {
+        let offset = (time - self.first_time).to_std().unwrap_or_default();
+        self.started + offset.div_f64(self.speed)
+    }
+}
+
+impl EegSource for ReplaySource {
+    fn n_channels(&self) -> usize {
+        self.n_channels
+    }
+
+    // Hands out every row that is due by now, waiting up to `timeout` for the next one otherwise.
+    fn pull_chunk(&mut self, buffer: &mut PullBuffer, timeout: f64) -> Result<usize, String> {
+        buffer.clear();
+        let deadline = Instant::now() + Duration::from_secs_f64(timeout);
+
+        loop {
+            let now = Instant::now();
+            while !buffer.is_full() {
+                if self.pending.is_empty() && self.read_until < self.end {
+                    self.read_page()?;
+                    continue;
+                }
+                match self.pending.front() {
+                    Some(row) if self.due_at(row.time) <= now => {
+                        let row = self.pending.pop_front().unwrap();
+                        buffer.push(datetime_to_unix_secs(row.time), &row.channels);
+                    }
+                    _ => break,
+                }
+            }
+            if !buffer.is_empty() || self.finished() {
+                return Ok(buffer.len());
+            }
+
+            // Nothing due yet, sleep until the next row is (or the timeout passes)
+            let next_due = self.pending.front().map(|row| self.due_at(row.time));
+            let wake = next_due.map_or(deadline, |due| due.min(deadline));
+            if now >= deadline {
+                return Ok(0);
+            }
+            std::thread::sleep(wake.saturating_duration_since(now));
+        }
+    }
+
+    // Gaps in the recording are replayed as silence, a replay can't be lost.
+    fn lost_after(&self) -> Duration {
+        Duration::MAX
+    }
+
+    fn finished(&self) -> bool {
+        self.pending.is_empty() && self.read_until >= self.end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::SourceConfig;
+
+    #[test]
+    fn test_replay_config_from_init_message() {
+        let source: SourceConfig = serde_json::from_str(
+            r#"{"type": "replay", "config": {"session_id": 7, "start": "2025-01-01T12:00:00Z"}}"#,
+        )
+        .unwrap();
+        let SourceConfig::Replay(config) = source else {
+            panic!("expected a replay source");
+        };
+        assert_eq!(config.session_id, 7);
+        assert_eq!(config.speed, 1.0);
+        assert!(config.end.is_none());
+        assert!(config.validate().is_ok());
+
+        let backwards = ReplayConfig {
+            end: Some(config.start.unwrap() - chrono::Duration::seconds(1)),
+            ..config.clone()
+        };
+        assert!(backwards.validate().is_err());
+        let stopped = ReplayConfig {
+            speed: 0.0,
+            ..config
+        };
+        assert!(stopped.validate().is_err());
+    }
+
+    #[test]
+    fn test_stored_samples_replay_at_full_precision() {
+        // more digits than an f32 holds
+        let stored = vec![123.456789012345, -0.000001234567891];
+        let mut buffer = PullBuffer::new(3);
+        buffer.push(1_700_000_000.0, &stored);
+        assert_eq!(buffer.value(0, 0), stored[0]);
+        assert_eq!(buffer.value(0, 1), stored[1]);
+        assert_eq!(buffer.value(0, 2), 0.0);
+    }
+}
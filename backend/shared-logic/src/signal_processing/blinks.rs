@@ -0,0 +1,456 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::NewTimeLabel;
+
+// Eye blink detection on the frontal channels, replacing the Eyeblink Python prototype.
+// Blinks are large, slow deflections (100-400 ms) that show up strongest on the electrodes
+// above the eyes, so the frontal channels are averaged, smoothed to remove everything above
+// the blink band and compared to a slowly tracking baseline.
+
+// Time constant of the baseline the deflections are measured from
+const BASELINE_SECS: f64 = 1.0;
+
+// Settings of a "blinkDetection" pipeline node. Detection is off without one.
+// Amplitudes are in microvolts, on the raw (unfiltered) signal.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct BlinkConfig {
+    // channels above the eyes, AF7 and AF8 on a Muse (TP9, AF7, AF8, TP10)
+    pub channels: Vec<usize>,
+    // smallest deflection from the baseline that counts as a blink
+    pub threshold: f64,
+    // the signal is smoothed with a moving average that removes rhythms above this
+    pub lowpass_hz: f64,
+    // deflections shorter than this are spikes, longer ones are eye movements or closures
+    pub min_duration_secs: f64,
+    pub max_duration_secs: f64,
+    // blinks closer than this to the previous one are its rebound, not a new blink
+    pub min_gap_secs: f64,
+    // store each blink as a time label of the session
+    pub store_labels: bool,
+    pub label: String,
+    pub color: String,
+}
+
+impl Default for BlinkConfig {
+    fn default() -> Self {
+        Self {
+            channels: vec![1, 2],
+            threshold: 50.0,
+            lowpass_hz: 10.0,
+            min_duration_secs: 0.05,
+            max_duration_secs: 1.0,
+            min_gap_secs: 0.25,
+            store_labels: false,
+            label: "blink".to_string(),
+            color: "teal-500".to_string(),
+        }
+    }
+}
+
+impl BlinkConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.channels.is_empty() {
+            return Err("blink detection needs at least one channel".to_string());
+        }
+        for (name, value) in [
+            ("threshold", self.threshold),
+            ("lowpass_hz", self.lowpass_hz),
+            ("max_duration_secs", self.max_duration_secs),
+        ] {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(format!("{} must be positive, got {}", name, value));
+            }
+        }
+        for (name, value) in [
+            ("min_duration_secs", self.min_duration_secs),
+            ("min_gap_secs", self.min_gap_secs),
+        ] {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(format!("{} must not be negative, got {}", name, value));
+            }
+        }
+        if self.min_duration_secs > self.max_duration_secs {
+            return Err("min_duration_secs must not be above max_duration_secs".to_string());
+        }
+        Ok(())
+    }
+
+    // A point label at the blink's peak
+    pub fn to_label(&self, blink: &BlinkEvent) -> NewTimeLabel {
+        NewTimeLabel {
+            start_timestamp: blink.timestamp,
+            end_timestamp: None,
+            label: self.label.clone(),
+            color: self.color.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlinkEvent {
+    // time of the peak
+    pub timestamp: DateTime<Utc>,
+    // peak deflection from the baseline in microvolts, negative for a trough
+    pub amplitude: f64,
+    // width at half the amplitude
+    pub duration_secs: f64,
+}
+
+// A deflection past half the threshold that may turn out to be a blink
+struct Deflection {
+    sign: f64,
+    peak: f64,
+    peak_time: DateTime<Utc>,
+    // deviations since the onset, for the width
+    deviations: Vec<f64>,
+}
+
+// Streaming blink detector, fed the new samples of each window. Blinks are reported by the
+// window they end in.
+pub struct BlinkDetector {
+    config: BlinkConfig,
+    sample_rate: f64,
+    // moving average of the frontal channels, with the timestamps of the averaged samples
+    smoothing: VecDeque<(f64, DateTime<Utc>)>,
+    smoothing_len: usize,
+    smoothing_sum: f64,
+    baseline: Option<f64>,
+    deflection: Option<Deflection>,
+    last_blink: Option<DateTime<Utc>>,
+}
+
+impl BlinkDetector {
+    pub fn new(config: BlinkConfig, sample_rate: f64, n_channels: usize) -> Result<Self, String> {
+        config.validate()?;
+        if let Some(&ch) = config.channels.iter().find(|&&ch| ch >= n_channels) {
+            return Err(format!(
+                "blink channel {} is out of range, the stream has {} channels",
+                ch, n_channels
+            ));
+        }
+        if !(sample_rate.is_finite() && sample_rate > 0.0) {
+            return Err(format!(
+                "Invalid sample rate for blink detection: {}",
+                sample_rate
+            ));
+        }
+        let smoothing_len = (sample_rate / config.lowpass_hz).round().max(1.0) as usize;
+        Ok(Self {
+            config,
+            sample_rate,
+            smoothing: VecDeque::with_capacity(smoothing_len),
+            smoothing_len,
+            smoothing_sum: 0.0,
+            baseline: None,
+            deflection: None,
+            last_blink: None,
+        })
+    }
+
+    pub fn config(&self) -> &BlinkConfig {
+        &self.config
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    // Forgets the signal so far, for when the stream was interrupted
+    pub fn reset(&mut self) {
+        self.smoothing.clear();
+        self.smoothing_sum = 0.0;
+        self.baseline = None;
+        self.deflection = None;
+    }
+
+    // Feeds samples (one Vec per channel) and returns the blinks that ended in them
+    pub fn process(
+        &mut self,
+        timestamps: &[DateTime<Utc>],
+        signals: &[Vec<f64>],
+    ) -> Vec<BlinkEvent> {
+        let mut blinks = Vec::new();
+        for (i, &time) in timestamps.iter().enumerate() {
+            let values: Vec<f64> = self
+                .config
+                .channels
+                .iter()
+                .filter_map(|&ch| signals.get(ch)?.get(i).copied())
+                .collect();
+            if values.is_empty() {
+                continue;
+            }
+            let frontal = values.iter().sum::<f64>() / values.len() as f64;
+
+            self.smoothing.push_back((frontal, time));
+            self.smoothing_sum += frontal;
+            if self.smoothing.len() > self.smoothing_len {
+                if let Some((old, _)) = self.smoothing.pop_front() {
+                    self.smoothing_sum -= old;
+                }
+            }
+            // the average describes the middle of the samples it covers
+            let smoothed = self.smoothing_sum / self.smoothing.len() as f64;
+            let center = self.smoothing[self.smoothing.len() / 2].1;
+
+            if let Some(blink) = self.step(smoothed, center) {
+                blinks.push(blink);
+            }
+        }
+        blinks
+    }
+
+    fn step(&mut self, value: f64, time: DateTime<Utc>) -> Option<BlinkEvent> {
+        let baseline = *self.baseline.get_or_insert(value);
+        let deviation = value - baseline;
+        let onset = self.config.threshold / 2.0;
+
+        let Some(deflection) = self.deflection.as_mut() else {
+            if deviation.abs() >= onset {
+                self.deflection = Some(Deflection {
+                    sign: deviation.signum(),
+                    peak: deviation,
+                    peak_time: time,
+                    deviations: vec![deviation],
+                });
+            } else {
+                // the baseline only follows the signal between deflections
+                let alpha = 1.0 / (BASELINE_SECS * self.sample_rate);
+                self.baseline = Some(baseline + alpha * deviation);
+            }
+            return None;
+        };
+
+        if deviation * deflection.sign >= onset {
+            if deviation.abs() > deflection.peak.abs() {
+                deflection.peak = deviation;
+                deflection.peak_time = time;
+            }
+            deflection.deviations.push(deviation);
+            // too long for a blink, the signal moved to a new level
+            if deflection.deviations.len() as f64 > self.config.max_duration_secs * self.sample_rate
+            {
+                self.deflection = None;
+                self.baseline = Some(value);
+            }
+            return None;
+        }
+
+        // back near the baseline, the deflection is over
+        let deflection = self.deflection.take()?;
+        let half = deflection.peak.abs() / 2.0;
+        let width = deflection
+            .deviations
+            .iter()
+            .filter(|d| d.abs() >= half)
+            .count();
+        let blink = BlinkEvent {
+            timestamp: deflection.peak_time,
+            amplitude: deflection.peak,
+            duration_secs: width as f64 / self.sample_rate,
+        };
+        let spaced = self.last_blink.is_none_or(|last| {
+            (blink.timestamp - last).num_milliseconds() as f64 / 1000.0 >= self.config.min_gap_secs
+        });
+        if blink.amplitude.abs() < self.config.threshold
+            || blink.duration_secs < self.config.min_duration_secs
+            || !spaced
+        {
+            return None;
+        }
+        self.last_blink = Some(blink.timestamp);
+        Some(blink)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mockeeg::{MockSignal, MockSignalConfig};
+    use std::f64::consts::PI;
+
+    // Port of get_metrics in api-server/python/Eyeblink/src/compute_accuracy.py, with
+    // times in seconds. Returns (precision, recall, F1) in percent.
+    fn get_metrics(
+        ground_truth: &[f64],
+        detected: &[f64],
+        tolerance: f64,
+        min_time: f64,
+    ) -> (f64, f64, f64) {
+        let mut matched = std::collections::HashSet::new();
+        let mut true_positives = 0;
+        for truth in ground_truth {
+            let within: Vec<usize> = (0..detected.len())
+                .filter(|&i| (detected[i] - truth).abs() <= tolerance && detected[i] > min_time)
+                .collect();
+            if !within.is_empty() {
+                true_positives += 1;
+                matched.extend(within);
+            }
+        }
+        let false_negatives = ground_truth.len() - true_positives;
+        let false_positives = detected.len() - matched.len();
+        let percent = |n: usize, d: usize| {
+            if d > 0 {
+                n as f64 * 100.0 / d as f64
+            } else {
+                0.0
+            }
+        };
+        let precision = percent(true_positives, true_positives + false_positives);
+        let recall = percent(true_positives, true_positives + false_negatives);
+        let f1 = if precision + recall > 0.0 {
+            2.0 * precision * recall / (precision + recall)
+        } else {
+            0.0
+        };
+        (precision, recall, f1)
+    }
+
+    // Runs the detector over whole recordings the way the pipeline does, in windows of 64
+    fn detect(signals: &[Vec<f64>], rate: f64, config: BlinkConfig) -> Vec<BlinkEvent> {
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let n = signals[0].len();
+        let timestamps: Vec<_> = (0..n)
+            .map(|i| start + chrono::Duration::microseconds((i as f64 / rate * 1e6) as i64))
+            .collect();
+        let mut detector = BlinkDetector::new(config, rate, signals.len()).unwrap();
+        let mut blinks = Vec::new();
+        for from in (0..n).step_by(64) {
+            let to = (from + 64).min(n);
+            let window: Vec<Vec<f64>> = signals.iter().map(|ch| ch[from..to].to_vec()).collect();
+            blinks.extend(detector.process(&timestamps[from..to], &window));
+        }
+        blinks
+    }
+
+    fn secs(blink: &BlinkEvent) -> f64 {
+        (blink.timestamp.timestamp_micros() - 1_700_000_000_000_000) as f64 / 1e6
+    }
+
+    // The EEG-Eyeblinks recordings compute_accuracy.py was run on aren't part of the repo, so
+    // the detector is scored the same way on mock EEG with blinks added at known times:
+    // pink noise, rhythms, line noise and muscle bursts on top of an electrode offset.
+    #[test]
+    fn test_detects_blinks_in_mock_eeg() {
+        let rate = 256.0;
+        let mut signal = MockSignal::new(
+            MockSignalConfig {
+                seed: Some(7),
+                blinks_per_minute: 0.0,
+                muscle_bursts_per_minute: 6.0,
+                line_noise: 10.0,
+                ..MockSignalConfig::default()
+            },
+            4,
+            rate,
+        );
+        let n = (60.0 * rate) as usize;
+        let mut signals = vec![Vec::new(); 4];
+        for _ in 0..n {
+            for (ch, v) in signals.iter_mut().zip(signal.next_sample()) {
+                ch.push(800.0 + v as f64);
+            }
+        }
+
+        // blinks of 80-160 µV and 200-400 ms every ~2.5s, some of them troughs
+        let truth: Vec<f64> = (0..22)
+            .map(|k| 2.0 + k as f64 * 2.5 + (k % 3) as f64 * 0.3)
+            .collect();
+        for (k, &t) in truth.iter().enumerate() {
+            let amplitude = (80.0 + 20.0 * (k % 5) as f64) * if k % 4 == 3 { -1.0 } else { 1.0 };
+            let len = 0.2 + 0.05 * (k % 5) as f64;
+            let from = ((t - len / 2.0) * rate) as usize;
+            for i in 0..(len * rate) as usize {
+                let bump = amplitude * (PI * i as f64 / (len * rate)).sin().powi(2);
+                // strongest on the frontal channels, weaker towards the temporal ones
+                for (ch, weight) in [0.3, 1.0, 0.9, 0.3].iter().enumerate() {
+                    signals[ch][from + i] += weight * bump;
+                }
+            }
+        }
+
+        let blinks = detect(&signals, rate, BlinkConfig::default());
+        let detected: Vec<f64> = blinks.iter().map(secs).collect();
+        let (precision, recall, f1) = get_metrics(&truth, &detected, 0.5, 0.5);
+        assert!(
+            f1 >= 95.0,
+            "precision {:.1}%, recall {:.1}%, F1 {:.1}%",
+            precision,
+            recall,
+            f1
+        );
+        for (blink, t) in blinks.iter().zip(&truth) {
+            assert!((secs(blink) - t).abs() < 0.05, "{:?} vs {}", blink, t);
+            assert!(blink.duration_secs > 0.05 && blink.duration_secs < 0.3);
+        }
+        assert!(blinks[3].amplitude < 0.0 && blinks[4].amplitude > 0.0);
+
+        // a step in the electrode offset is not a blink
+        let step: Vec<Vec<f64>> = (0..4)
+            .map(|_| {
+                (0..n)
+                    .map(|i| if i < n / 2 { 0.0 } else { 300.0 })
+                    .collect()
+            })
+            .collect();
+        assert!(detect(&step, rate, BlinkConfig::default()).is_empty());
+    }
+
+    // Scores the detector on a recording of the EEG-Eyeblinks dataset (EEG-VR or EEG-VV), the
+    // data compute_accuracy.py reports on, with its labels file next to it:
+    //   EEG_BLINK_DATA=EEG-VR/S00R_data.csv cargo test -p shared-logic -- --ignored
+    #[test]
+    #[ignore]
+    fn test_matches_eyeblink_reference_data() {
+        let data_path = std::env::var("EEG_BLINK_DATA").expect("EEG_BLINK_DATA not set");
+        let labels_path = data_path.replace("_data", "_labels");
+        let rate = 250.0;
+
+        // time, Fp1, Fp2 after a 5 line header; the prototype reads the first 200s
+        let data = std::fs::read_to_string(&data_path).unwrap();
+        let mut signals = vec![Vec::new(), Vec::new()];
+        for line in data.lines().skip(5).take((200.0 * rate) as usize + 1) {
+            let fields: Vec<f64> = line
+                .split(',')
+                .take(3)
+                .map(|v| v.trim().parse().unwrap())
+                .collect();
+            signals[0].push(fields[1]);
+            signals[1].push(fields[2]);
+        }
+
+        // "corrupt,<n>", n intervals, "blinks", then one "<time>,<code>" line per blink
+        let labels = std::fs::read_to_string(&labels_path).unwrap();
+        let mut truth = Vec::new();
+        let mut in_blinks = false;
+        for line in labels.lines() {
+            let first = line.split(',').next().unwrap_or("").trim();
+            if first == "blinks" {
+                in_blinks = true;
+            } else if in_blinks {
+                truth.push(first.parse::<f64>().unwrap());
+            }
+        }
+
+        let config = BlinkConfig {
+            channels: vec![0, 1],
+            ..BlinkConfig::default()
+        };
+        let detected: Vec<f64> = detect(&signals, rate, config).iter().map(secs).collect();
+        let (precision, recall, f1) = get_metrics(&truth, &detected, 0.5, 0.5);
+        println!(
+            "{} blinks, {} detected: precision {:.1}%, recall {:.1}%, F1 {:.1}%",
+            truth.len(),
+            detected.len(),
+            precision,
+            recall,
+            f1
+        );
+        assert!(f1 >= 80.0);
+    }
+}
@@ -0,0 +1,746 @@
+// Native Rust bandpass filters.
+//
+// These mirror the two filters in signalProcessing.py so a window can be filtered without
+// going through Python:
+//   FIR → mne.filter.filter_data(method="fir") with MNE's default ("auto") parameters
+//   IIR → scipy.signal.butter(N=4, btype="bandpass", output="sos") + sosfiltfilt
+//
+// BandpassFilter::apply is zero-phase and filters one block of signals shaped
+// (n_channels, n_samples) on its own. StreamingBandpass runs the same designs causally and
+// keeps the filter state between calls, for filtering a live stream window by window.
+use std::f64::consts::PI;
+
+use num_complex::Complex64;
+
+use crate::pipeline::PreprocessingConfig;
+
+// Cutoffs used when the config leaves them unset, same fallbacks as the Python calls.
+const DEFAULT_L_FREQ: f64 = 1.0;
+const DEFAULT_H_FREQ: f64 = 50.0;
+
+// signalProcessing.iir_bandpass_filter uses a 4th order Butterworth.
+const IIR_ORDER: usize = 4;
+
+// MNE's filter length factor for the hamming window (mne.filter._length_factors).
+const HAMMING_LENGTH_FACTOR: f64 = 3.3;
+
+// One second-order section laid out like a row of scipy's `sos` array:
+// `[b0, b1, b2, a0, a1, a2]`, with `a0` normalised to 1.
+pub type Sos = [f64; 6];
+
+#[derive(Debug, Clone)]
+pub enum BandpassFilter {
+    Fir { taps: Vec<f64> },
+    Iir { sections: Vec<Sos> },
+}
+
+impl BandpassFilter {
+    // Designs the bandpass described by `config` (`use_iir`, `l_freq`, `h_freq`, `sfreq`).
+    pub fn from_config(config: &PreprocessingConfig) -> Result<Self, String> {
+        let sfreq = config.sfreq as f64;
+        let l_freq = config.l_freq.map(f64::from).unwrap_or(DEFAULT_L_FREQ);
+        let h_freq = config.h_freq.map(f64::from).unwrap_or(DEFAULT_H_FREQ);
+
+        if config.use_iir {
+            Ok(Self::Iir {
+                sections: design_iir_bandpass(sfreq, l_freq, h_freq, IIR_ORDER)?,
+            })
+        } else {
+            Ok(Self::Fir {
+                taps: design_fir_bandpass(sfreq, l_freq, h_freq)?,
+            })
+        }
+    }
+
+    // Applies the filter forwards and backwards to every channel of one window.
+    pub fn apply(&self, signals: &[Vec<f64>]) -> Result<Vec<Vec<f64>>, String> {
+        signals
+            .iter()
+            .map(|channel| match self {
+                Self::Fir { taps } => Ok(fir_filter_zero_phase(taps, channel)),
+                Self::Iir { sections } => sosfiltfilt(sections, channel),
+            })
+            .collect()
+    }
+}
+
+fn validate_band(sfreq: f64, l_freq: f64, h_freq: f64) -> Result<(), String> {
+    let nyquist = sfreq / 2.0;
+    if sfreq <= 0.0 {
+        return Err(format!("Sampling rate must be positive, got {}", sfreq));
+    }
+    if l_freq <= 0.0 || h_freq <= l_freq || h_freq >= nyquist {
+        return Err(format!(
+            "Invalid bandpass {}-{} Hz: need 0 < l_freq < h_freq < {} Hz (Nyquist)",
+            l_freq, h_freq, nyquist
+        ));
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// FIR (windowed-sinc), following MNE's firwin design
+// ---------------------------------------------------------------------------
+
+// Designs the zero-phase FIR bandpass MNE builds for `filter_data(method="fir")`:
+// "auto" transition bandwidths and filter length, hamming window, firwin design.
+pub fn design_fir_bandpass(sfreq: f64, l_freq: f64, h_freq: f64) -> Result<Vec<f64>, String> {
+    validate_band(sfreq, l_freq, h_freq)?;
+    let nyquist = sfreq / 2.0;
+
+    let l_trans = (0.25 * l_freq).max(2.0).min(l_freq);
+    let h_trans = (0.25 * h_freq).max(2.0).min(nyquist - h_freq);
+
+    let mut n_taps = ((HAMMING_LENGTH_FACTOR / l_trans.min(h_trans)) * sfreq).ceil() as usize;
+    n_taps = n_taps.max(1);
+    n_taps += (n_taps - 1) % 2; // firwin needs an odd length
+
+    // Piecewise gain description, exactly as mne.filter.create_filter builds it
+    let l_stop = l_freq - l_trans;
+    let h_stop = h_freq + h_trans;
+    let mut freq = vec![l_stop, l_freq, h_freq, h_stop];
+    let mut gain = vec![0.0, 1.0, 1.0, 0.0];
+    if l_stop != 0.0 {
+        freq.insert(0, 0.0);
+        gain.insert(0, 0.0);
+    }
+    if h_stop != nyquist {
+        freq.push(nyquist);
+        gain.push(0.0);
+    }
+    let freq: Vec<f64> = freq.iter().map(|f| f / nyquist).collect();
+
+    // Walk the edges from Nyquist down, adding or subtracting a lowpass at each transition
+    let mut taps = vec![0.0; n_taps];
+    let mut prev_freq = freq[freq.len() - 1];
+    let mut prev_gain = gain[gain.len() - 1];
+    if prev_gain == 1.0 {
+        taps[n_taps / 2] = 1.0;
+    }
+    for (&this_freq, &this_gain) in freq.iter().zip(gain.iter()).rev().skip(1) {
+        if this_gain != prev_gain {
+            let transition = (prev_freq - this_freq) / 2.0;
+            let mut this_n = (HAMMING_LENGTH_FACTOR / transition).round_ties_even() as usize;
+            this_n += 1 - this_n % 2;
+            if this_n > n_taps {
+                return Err(format!(
+                    "Transition at {} Hz needs {} taps, filter only has {}",
+                    this_freq * nyquist,
+                    this_n,
+                    n_taps
+                ));
+            }
+            let lowpass = firwin_lowpass(this_n, (prev_freq + this_freq) / 2.0);
+            let offset = (n_taps - this_n) / 2;
+            for (tap, lp) in taps[offset..n_taps - offset].iter_mut().zip(&lowpass) {
+                if this_gain == 0.0 {
+                    *tap -= lp;
+                } else {
+                    *tap += lp;
+                }
+            }
+        }
+        prev_gain = this_gain;
+        prev_freq = this_freq;
+    }
+
+    Ok(taps)
+}
+
+// scipy.signal.firwin(n_taps, cutoff, window="hamming", pass_zero=True), with cutoff
+// normalised to Nyquist. Scaled for unit gain at DC.
+fn firwin_lowpass(n_taps: usize, cutoff: f64) -> Vec<f64> {
+    let alpha = 0.5 * (n_taps - 1) as f64;
+    let mut taps: Vec<f64> = (0..n_taps)
+        .map(|i| {
+            let m = i as f64 - alpha;
+            cutoff * sinc(cutoff * m) * hamming(i, n_taps)
+        })
+        .collect();
+    let sum: f64 = taps.iter().sum();
+    for tap in &mut taps {
+        *tap /= sum;
+    }
+    taps
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+// Symmetric hamming window value at index i of n.
+fn hamming(i: usize, n: usize) -> f64 {
+    if n == 1 {
+        return 1.0;
+    }
+    0.54 - 0.46 * (2.0 * PI * i as f64 / (n - 1) as f64).cos()
+}
+
+// Zero-phase FIR filtering as done by MNE's overlap-add path: odd-reflect the edges
+// ("reflect_limited"), convolve, and compensate the (n_taps - 1) / 2 group delay.
+fn fir_filter_zero_phase(taps: &[f64], x: &[f64]) -> Vec<f64> {
+    if x.is_empty() {
+        return Vec::new();
+    }
+    let n_edge = taps.len().min(x.len()).saturating_sub(1);
+    let extended = reflect_limited_pad(x, n_edge);
+    let shift = (taps.len() - 1) / 2 + n_edge;
+
+    (0..x.len())
+        .map(|i| {
+            let centre = i + shift;
+            taps.iter()
+                .enumerate()
+                .filter_map(|(k, tap)| {
+                    centre
+                        .checked_sub(k)
+                        .and_then(|idx| extended.get(idx))
+                        .map(|v| tap * v)
+                })
+                .sum()
+        })
+        .collect()
+}
+
+// mne.filter._smart_pad(x, (n_pad, n_pad), "reflect_limited")
+fn reflect_limited_pad(x: &[f64], n_pad: usize) -> Vec<f64> {
+    let n = x.len();
+    let zero_pad = (n_pad + 1).saturating_sub(n);
+    let first = x[0];
+    let last = x[n - 1];
+
+    let mut out = Vec::with_capacity(n + 2 * n_pad);
+    out.extend(std::iter::repeat_n(0.0, zero_pad));
+    out.extend((1..=n_pad.min(n - 1)).rev().map(|i| 2.0 * first - x[i]));
+    out.extend_from_slice(x);
+    out.extend((1..=n_pad.min(n - 1)).map(|i| 2.0 * last - x[n - 1 - i]));
+    out.extend(std::iter::repeat_n(0.0, zero_pad));
+    out
+}
+
+// ---------------------------------------------------------------------------
+// IIR (Butterworth, second-order sections), following scipy.signal.butter
+// ---------------------------------------------------------------------------
+
+// Designs a digital Butterworth bandpass of the given prototype order as second-order
+// sections (the result has `order` sections, i.e. filter order `2 * order`).
+pub fn design_iir_bandpass(
+    sfreq: f64,
+    l_freq: f64,
+    h_freq: f64,
+    order: usize,
+) -> Result<Vec<Sos>, String> {
+    validate_band(sfreq, l_freq, h_freq)?;
+    if order == 0 {
+        return Err("Butterworth order must be at least 1".to_string());
+    }
+
+    // Pre-warp the band edges for the bilinear transform (fs normalised to 2)
+    let warp = |f: f64| 4.0 * (PI * (2.0 * f / sfreq) / 2.0).tan();
+    let (low, high) = (warp(l_freq), warp(h_freq));
+    let bw = high - low;
+    let wo = (low * high).sqrt();
+
+    // Analog lowpass prototype poles, then lowpass → bandpass
+    let mut analog_poles = Vec::with_capacity(2 * order);
+    for m in (0..order).map(|i| 2 * i as i64 - order as i64 + 1) {
+        let p = -Complex64::from_polar(1.0, PI * m as f64 / (2 * order) as f64);
+        let p_lp = p * bw / 2.0;
+        let root = (p_lp * p_lp - wo * wo).sqrt();
+        analog_poles.push(p_lp + root);
+        analog_poles.push(p_lp - root);
+    }
+
+    // Bilinear transform. The `order` analog zeros at 0 map to z = 1 and the remaining
+    // `order` zeros at infinity map to z = -1.
+    let fs2 = Complex64::new(4.0, 0.0);
+    let poles: Vec<Complex64> = analog_poles.iter().map(|p| (fs2 + p) / (fs2 - p)).collect();
+    let denom: Complex64 = analog_poles.iter().map(|p| fs2 - p).product();
+    let gain = bw.powi(order as i32) * (fs2.powi(order as i32) / denom).re;
+
+    let mut pole_pairs = pair_poles(poles);
+    // Like scipy, put the poles closest to the unit circle in the last sections
+    pole_pairs.sort_by(|a, b| a.0.norm().total_cmp(&b.0.norm()));
+
+    let mut sections: Vec<Sos> = pole_pairs
+        .iter()
+        .map(|(p1, p2)| {
+            let a1 = -(p1 + p2).re;
+            let a2 = (p1 * p2).re;
+            // Each section takes one zero at z = 1 and one at z = -1
+            [1.0, 0.0, -1.0, 1.0, a1, a2]
+        })
+        .collect();
+    for b in &mut sections[0][..3] {
+        *b *= gain;
+    }
+
+    Ok(sections)
+}
+
+// Groups poles into conjugate pairs (or pairs of real poles) for second-order sections.
+fn pair_poles(poles: Vec<Complex64>) -> Vec<(Complex64, Complex64)> {
+    const EPS: f64 = 1e-12;
+    let mut pairs = Vec::with_capacity(poles.len() / 2);
+    let mut real = Vec::new();
+    for p in poles {
+        if p.im > EPS {
+            pairs.push((p, p.conj()));
+        } else if p.im.abs() <= EPS {
+            real.push(Complex64::new(p.re, 0.0));
+        }
+    }
+    for chunk in real.chunks(2) {
+        match chunk {
+            [p1, p2] => pairs.push((*p1, *p2)),
+            [p] => pairs.push((*p, Complex64::new(0.0, 0.0))),
+            _ => {}
+        }
+    }
+    pairs
+}
+
+// Runs a cascade of second-order sections (direct form II transposed) over `x`,
+// updating the per-section state `zi` in place. Same recurrence as scipy.signal.sosfilt.
+fn sosfilt(sections: &[Sos], x: &[f64], zi: &mut [[f64; 2]]) -> Vec<f64> {
+    let mut y = x.to_vec();
+    for (sos, z) in sections.iter().zip(zi.iter_mut()) {
+        let [b0, b1, b2, _, a1, a2] = *sos;
+        for sample in y.iter_mut() {
+            let input = *sample;
+            let output = b0 * input + z[0];
+            z[0] = b1 * input - a1 * output + z[1];
+            z[1] = b2 * input - a2 * output;
+            *sample = output;
+        }
+    }
+    y
+}
+
+// Steady-state section state for a unit step input (scipy.signal.sosfilt_zi).
+fn sosfilt_zi(sections: &[Sos]) -> Vec<[f64; 2]> {
+    let mut scale = 1.0;
+    sections
+        .iter()
+        .map(|&[b0, b1, b2, _, a1, a2]| {
+            let dc_gain = (b0 + b1 + b2) / (1.0 + a1 + a2);
+            let zi = [
+                scale * (b1 + b2 - (a1 + a2) * dc_gain),
+                scale * (b2 - a2 * dc_gain),
+            ];
+            scale *= dc_gain;
+            zi
+        })
+        .collect()
+}
+
+// scipy.signal.sosfiltfilt with its default odd padding.
+fn sosfiltfilt(sections: &[Sos], x: &[f64]) -> Result<Vec<f64>, String> {
+    let zero_b2 = sections.iter().filter(|s| s[2] == 0.0).count();
+    let zero_a2 = sections.iter().filter(|s| s[5] == 0.0).count();
+    let padlen = 3 * (2 * sections.len() + 1 - zero_b2.min(zero_a2));
+    if x.len() <= padlen {
+        return Err(format!(
+            "IIR filtering needs more than {} samples per window, got {}",
+            padlen,
+            x.len()
+        ));
+    }
+
+    let n = x.len();
+    let mut extended = Vec::with_capacity(n + 2 * padlen);
+    extended.extend((1..=padlen).rev().map(|i| 2.0 * x[0] - x[i]));
+    extended.extend_from_slice(x);
+    extended.extend((1..=padlen).map(|i| 2.0 * x[n - 1] - x[n - 1 - i]));
+
+    let zi = sosfilt_zi(sections);
+    let scaled = |v: f64| zi.iter().map(|z| [z[0] * v, z[1] * v]).collect::<Vec<_>>();
+
+    let mut forward = sosfilt(sections, &extended, &mut scaled(extended[0]));
+    forward.reverse();
+    let mut backward = sosfilt(sections, &forward, &mut scaled(forward[0]));
+    backward.reverse();
+
+    Ok(backward[padlen..padlen + n].to_vec())
+}
+
+// ---------------------------------------------------------------------------
+// Streaming (stateful) filtering
+// ---------------------------------------------------------------------------
+
+// Per-channel filter memory that survives between windows.
+#[derive(Debug, Clone)]
+enum ChannelState {
+    // IIR: direct form II transposed delay line of every section
+    Iir(Vec<[f64; 2]>),
+    // FIR: the last n_taps - 1 input samples
+    Fir(Vec<f64>),
+}
+
+// Causal version of a [`BandpassFilter`] that carries its state across calls, so
+// consecutive windows of a stream are filtered as one continuous signal instead of each
+// getting its own edge transient.
+//
+// The state is seeded from the first sample each channel sees (as if the signal had been
+// constant before it), which avoids a step transient at the start of a session.
+// Being causal, the output lags the input by `group_delay_samples`.
+#[derive(Debug, Clone)]
+pub struct StreamingBandpass {
+    filter: BandpassFilter,
+    channels: Vec<Option<ChannelState>>,
+}
+
+impl StreamingBandpass {
+    pub fn new(filter: BandpassFilter) -> Self {
+        Self {
+            filter,
+            channels: Vec::new(),
+        }
+    }
+
+    pub fn from_config(config: &PreprocessingConfig) -> Result<Self, String> {
+        BandpassFilter::from_config(config).map(Self::new)
+    }
+
+    // Filters the next block of samples of every channel in place.
+    pub fn process(&mut self, signals: &mut [Vec<f64>]) {
+        if self.channels.len() != signals.len() {
+            self.channels = vec![None; signals.len()];
+        }
+
+        for (channel, state) in signals.iter_mut().zip(self.channels.iter_mut()) {
+            let Some(&first) = channel.first() else {
+                continue;
+            };
+            let state = state.get_or_insert_with(|| match &self.filter {
+                BandpassFilter::Iir { sections } => ChannelState::Iir(
+                    sosfilt_zi(sections)
+                        .iter()
+                        .map(|z| [z[0] * first, z[1] * first])
+                        .collect(),
+                ),
+                BandpassFilter::Fir { taps } => ChannelState::Fir(vec![first; taps.len() - 1]),
+            });
+
+            *channel = match (&self.filter, state) {
+                (BandpassFilter::Iir { sections }, ChannelState::Iir(zi)) => {
+                    sosfilt(sections, channel, zi)
+                }
+                (BandpassFilter::Fir { taps }, ChannelState::Fir(history)) => {
+                    fir_filter_streaming(taps, channel, history)
+                }
+                _ => unreachable!("channel state always matches the filter kind"),
+            };
+        }
+    }
+
+    // Drops all filter memory; the next call to `process` starts a fresh stream.
+    pub fn reset(&mut self) {
+        self.channels.clear();
+    }
+
+    // How many samples the output lags the input by. Exact for the linear-phase FIR; the
+    // IIR's delay depends on frequency and is taken where its gain peaks, in the passband.
+    pub fn group_delay_samples(&self) -> f64 {
+        match &self.filter {
+            BandpassFilter::Fir { taps } => (taps.len() - 1) as f64 / 2.0,
+            BandpassFilter::Iir { sections } => {
+                // search the normalised frequencies 0..pi for the gain peak
+                const GRID: usize = 2048;
+                let response = |w: f64| {
+                    let z = Complex64::from_polar(1.0, -w);
+                    sections
+                        .iter()
+                        .map(|s| {
+                            (s[0] + s[1] * z + s[2] * z * z) / (s[3] + s[4] * z + s[5] * z * z)
+                        })
+                        .product::<Complex64>()
+                        .norm()
+                };
+                let peak = (1..GRID)
+                    .map(|i| PI * i as f64 / GRID as f64)
+                    .max_by(|a, b| response(*a).total_cmp(&response(*b)))
+                    .unwrap_or(0.0);
+                sections
+                    .iter()
+                    .map(|s| {
+                        polynomial_group_delay(&s[..3], peak)
+                            - polynomial_group_delay(&s[3..], peak)
+                    })
+                    .sum()
+            }
+        }
+    }
+}
+
+// Group delay in samples of the polynomial c0 + c1 z^-1 + c2 z^-2 + ... at normalised
+// frequency w.
+fn polynomial_group_delay(coefficients: &[f64], w: f64) -> f64 {
+    let (weighted, plain) = coefficients.iter().enumerate().fold(
+        (Complex64::new(0.0, 0.0), Complex64::new(0.0, 0.0)),
+        |(weighted, plain), (k, c)| {
+            let term = c * Complex64::from_polar(1.0, -w * k as f64);
+            (weighted + term * k as f64, plain + term)
+        },
+    );
+    (weighted / plain).re
+}
+
+// Causal FIR filtering of `x`, continuing from (and updating) the previous input `history`.
+fn fir_filter_streaming(taps: &[f64], x: &[f64], history: &mut Vec<f64>) -> Vec<f64> {
+    let n_history = taps.len() - 1;
+    let mut buffer = std::mem::take(history);
+    buffer.extend_from_slice(x);
+
+    let output = (0..x.len())
+        .map(|i| {
+            taps.iter()
+                .enumerate()
+                .map(|(k, tap)| tap * buffer[i + n_history - k])
+                .sum()
+        })
+        .collect();
+
+    buffer.drain(..buffer.len() - n_history);
+    *history = buffer;
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // |H(e^jw)| of a cascade of sections at `freq` Hz
+    fn sos_gain(sections: &[Sos], freq: f64, sfreq: f64) -> f64 {
+        let z = Complex64::from_polar(1.0, -2.0 * PI * freq / sfreq);
+        sections
+            .iter()
+            .map(|s| {
+                let num = s[0] + s[1] * z + s[2] * z * z;
+                let den = s[3] + s[4] * z + s[5] * z * z;
+                (num / den).norm()
+            })
+            .product()
+    }
+
+    fn fir_gain(taps: &[f64], freq: f64, sfreq: f64) -> f64 {
+        taps.iter()
+            .enumerate()
+            .map(|(k, t)| t * Complex64::from_polar(1.0, -2.0 * PI * freq * k as f64 / sfreq))
+            .sum::<Complex64>()
+            .norm()
+    }
+
+    fn sine(freq: f64, sfreq: f64, n: usize) -> Vec<f64> {
+        (0..n)
+            .map(|i| (2.0 * PI * freq * i as f64 / sfreq).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_iir_butterworth_response() {
+        let sections = design_iir_bandpass(256.0, 1.0, 50.0, 4).unwrap();
+        assert_eq!(sections.len(), 4);
+        // Butterworth: -3 dB exactly at the (pre-warped) band edges
+        assert!((sos_gain(&sections, 1.0, 256.0) - 0.5_f64.sqrt()).abs() < 1e-6);
+        assert!((sos_gain(&sections, 50.0, 256.0) - 0.5_f64.sqrt()).abs() < 1e-6);
+        assert!((sos_gain(&sections, 10.0, 256.0) - 1.0).abs() < 1e-3);
+        assert!(sos_gain(&sections, 0.1, 256.0) < 1e-3);
+        assert!(sos_gain(&sections, 100.0, 256.0) < 1e-2);
+    }
+
+    #[test]
+    fn test_fir_matches_mne_auto_design() {
+        // MNE picks a 1 Hz lower transition → 3.3 s at 256 Hz → 845 taps
+        let taps = design_fir_bandpass(256.0, 1.0, 50.0).unwrap();
+        assert_eq!(taps.len(), 845);
+        // symmetric (linear phase)
+        for i in 0..taps.len() / 2 {
+            assert!((taps[i] - taps[taps.len() - 1 - i]).abs() < 1e-15);
+        }
+        assert!((fir_gain(&taps, 10.0, 256.0) - 1.0).abs() < 1e-2);
+        assert!(fir_gain(&taps, 0.0, 256.0) < 1e-2);
+        assert!(fir_gain(&taps, 80.0, 256.0) < 1e-2);
+    }
+
+    #[test]
+    fn test_zero_phase_filters_pass_in_band_sine() {
+        let sfreq = 256.0;
+        let signal = sine(10.0, sfreq, 1024);
+        for use_iir in [false, true] {
+            let config = PreprocessingConfig {
+                use_iir,
+                ..PreprocessingConfig::default()
+            };
+            let filter = BandpassFilter::from_config(&config).unwrap();
+            let out = filter.apply(std::slice::from_ref(&signal)).unwrap();
+            // away from the edges the in-band sine comes through unchanged and unshifted
+            for i in 300..700 {
+                assert!(
+                    (out[0][i] - signal[i]).abs() < 2e-2,
+                    "use_iir={} sample {}: {} vs {}",
+                    use_iir,
+                    i,
+                    out[0][i],
+                    signal[i]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_rejects_invalid_band() {
+        assert!(design_fir_bandpass(256.0, 50.0, 1.0).is_err());
+        assert!(design_iir_bandpass(256.0, 1.0, 128.0, 4).is_err());
+        assert!(design_iir_bandpass(256.0, 0.0, 50.0, 4).is_err());
+    }
+
+    #[test]
+    fn test_iir_requires_more_than_padlen_samples() {
+        let sections = design_iir_bandpass(256.0, 1.0, 50.0, 4).unwrap();
+        assert!(sosfiltfilt(&sections, &[0.0; 27]).is_err());
+        assert!(sosfiltfilt(&sections, &[0.0; 28]).is_ok());
+    }
+
+    #[test]
+    fn test_streaming_is_continuous_across_windows() {
+        let sfreq = 256.0;
+        let signal: Vec<f64> = sine(10.0, sfreq, 1024)
+            .iter()
+            .zip(sine(0.2, sfreq, 1024))
+            .map(|(a, drift)| a + 5.0 * drift)
+            .collect();
+        for use_iir in [false, true] {
+            let config = PreprocessingConfig {
+                use_iir,
+                ..PreprocessingConfig::default()
+            };
+
+            let mut whole = vec![signal.clone()];
+            StreamingBandpass::from_config(&config)
+                .unwrap()
+                .process(&mut whole);
+
+            let mut streaming = StreamingBandpass::from_config(&config).unwrap();
+            let mut windowed = Vec::new();
+            for chunk in signal.chunks(64) {
+                let mut window = vec![chunk.to_vec()];
+                streaming.process(&mut window);
+                windowed.extend(window.remove(0));
+            }
+
+            // Filtering 64-sample windows with carried state is the same as filtering it whole
+            for (a, b) in windowed.iter().zip(&whole[0]) {
+                assert!((a - b).abs() < 1e-9, "use_iir={}: {} vs {}", use_iir, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_streaming_reset_starts_fresh() {
+        let config = PreprocessingConfig {
+            use_iir: true,
+            ..PreprocessingConfig::default()
+        };
+        let mut streaming = StreamingBandpass::from_config(&config).unwrap();
+        let mut first = vec![sine(10.0, 256.0, 64)];
+        let expected = first.clone();
+        streaming.process(&mut first);
+
+        let mut again = expected.clone();
+        streaming.reset();
+        streaming.process(&mut again);
+        assert_eq!(first, again);
+    }
+
+    #[test]
+    fn test_streaming_group_delay_matches_output_lag() {
+        // a 10 Hz burst whose envelope peaks at sample 600
+        let sfreq = 256.0;
+        let burst: Vec<f64> = (0..2048)
+            .map(|i| {
+                let t = (i as f64 - 600.0) / 40.0;
+                (-t * t / 2.0).exp() * (2.0 * PI * 10.0 * i as f64 / sfreq).sin()
+            })
+            .collect();
+
+        for use_iir in [false, true] {
+            let config = PreprocessingConfig {
+                use_iir,
+                n_channels: 1,
+                ..PreprocessingConfig::default()
+            };
+            let mut filter = StreamingBandpass::from_config(&config).unwrap();
+            let mut signals = vec![burst.clone()];
+            filter.process(&mut signals);
+
+            // the envelope peak is where the rectified output is largest, within one cycle
+            let envelope: Vec<f64> = signals[0]
+                .windows(26)
+                .map(|w| w.iter().map(|v| v.abs()).sum())
+                .collect();
+            let peak = envelope
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(b.1))
+                .map(|(i, _)| i as f64 + 12.5)
+                .unwrap();
+            let lag = peak - 600.0;
+            assert!(
+                (lag - filter.group_delay_samples()).abs() < 3.0,
+                "use_iir={}: output lags {} samples, reported {}",
+                use_iir,
+                lag,
+                filter.group_delay_samples()
+            );
+        }
+    }
+
+    // Compares against the MNE (FIR) and scipy (IIR) output of signalProcessing.py for one
+    // window. testdata/filter_reference.csv isn't committed yet; generate it with
+    //   python src/signal_processing/testdata/make_filter_reference.py
+    // in an environment with numpy, mne and scipy, commit it and drop the ignore.
+    #[test]
+    #[ignore = "needs testdata/filter_reference.csv from make_filter_reference.py"]
+    fn test_matches_python_signal_processing() {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/signal_processing/testdata/filter_reference.csv"
+        );
+        let mut reader = csv::Reader::from_path(path).unwrap();
+        let mut columns: Vec<Vec<f64>> = vec![Vec::new(); 6];
+        for record in reader.records() {
+            let record = record.unwrap();
+            for (column, value) in columns.iter_mut().zip(record.iter()) {
+                column.push(value.parse().unwrap());
+            }
+        }
+        let input = columns[0..2].to_vec();
+
+        for (use_iir, expected) in [(false, &columns[2..4]), (true, &columns[4..6])] {
+            let config = PreprocessingConfig {
+                use_iir,
+                n_channels: input.len(),
+                ..PreprocessingConfig::default()
+            };
+            let ours = BandpassFilter::from_config(&config)
+                .unwrap()
+                .apply(&input)
+                .unwrap();
+            for (a, b) in ours.iter().flatten().zip(expected.iter().flatten()) {
+                let tolerance = 1e-6 * b.abs().max(1.0);
+                assert!(
+                    (a - b).abs() < tolerance,
+                    "use_iir={}: {} vs {}",
+                    use_iir,
+                    a,
+                    b
+                );
+            }
+        }
+    }
+}
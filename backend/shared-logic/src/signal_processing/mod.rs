@@ -1,2 +1,6 @@
+pub mod blinks;
+pub mod filters;
 pub mod pipeline_gateway;
+pub mod quality;
 pub mod signal_processor;
+pub mod spectrum;
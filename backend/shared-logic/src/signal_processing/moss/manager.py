@@ -126,7 +126,10 @@ async def bandpass_filter(data, config):
     # signalProcessing bandpass functions expect (n_channels, n_samples), so transpose first
     channels_first = np.asarray(data.T, dtype=np.float64)
     normalized_method = method.strip().upper()
-    if normalized_method == "FIR":
+    if config.get("prefiltered", False):
+        # the rust side already applied this bandpass, don't filter twice
+        filtered_channels_first = channels_first
+    elif normalized_method == "FIR":
         filtered_channels_first = await asyncio.to_thread(
             signalProcessing.fir_bandpass_filter,
             channels_first,
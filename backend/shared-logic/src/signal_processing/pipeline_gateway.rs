@@ -4,6 +4,7 @@ use pyo3::types::{PyDict, PyList, PyModule};
 use serde::{Deserialize, Serialize};
 
 use crate::lsl::ProcessingConfig;
+use crate::pipeline::MLConfig;
 
 pub struct PipelineGateway {
     manager_module: Py<PyModule>,
@@ -34,15 +35,16 @@ impl PipelineGateway {
     pub fn call_pipeline(
         &self,
         config: &ProcessingConfig,
+        ml: Option<&MLConfig>,
         signals: &[Vec<f64>],
     ) -> Result<Option<PipelineOutput>, String> {
         Python::with_gil(|py| {
             let module = self.manager_module.as_ref(py);
 
-            // Translate ProcessingConfig into the dict format manager.py expects
-            let pipeline_dict = build_python_pipeline_dict(py, config)?;
+            // Translate ProcessingConfig and the ML node into the dict format manager.py expects
+            let pipeline_dict = build_python_pipeline_dict(py, config, ml)?;
 
-            // Transpose signals from (4, n_samples) → (n_samples, 4) as manager.py expects
+            // Transpose signals from (n_channels, n_samples) → (n_samples, n_channels) as manager.py expects
             let transposed = transpose_signals(signals);
             let np_array = PyArray2::from_vec2(py, &transposed)
                 .map_err(|e| format!("Failed to create numpy array: {}", e))?;
@@ -92,9 +94,12 @@ impl PipelineGateway {
 //   Rust ProcessingConfig.h_freq  → Python config "Filter"
 //   Rust ProcessingConfig.l_freq  → Python config "low_cut_hz"
 //   Rust ProcessingConfig.use_iir → Python config "method" ("IIR" or "FIR")
+// The signals are already bandpassed in Rust (see signal_processing::filters), so the node is
+// sent with "prefiltered" set and manager.py only resamples/segments them.
 fn build_python_pipeline_dict<'py>(
     py: Python<'py>,
     config: &ProcessingConfig,
+    ml: Option<&MLConfig>,
 ) -> Result<&'py PyDict, String> {
     let nodes_list = PyList::empty(py);
 
@@ -112,6 +117,9 @@ fn build_python_pipeline_dict<'py>(
         bandpass_config
             .set_item("src_fs", config.sfreq)
             .map_err(|e| format!("Failed to set src_fs: {}", e))?;
+        bandpass_config
+            .set_item("prefiltered", true)
+            .map_err(|e| format!("Failed to set prefiltered: {}", e))?;
 
         let node_dict = PyDict::new(py);
         node_dict
@@ -125,6 +133,25 @@ fn build_python_pipeline_dict<'py>(
             .map_err(|e| format!("Failed to append bandpass node: {}", e))?;
     }
 
+    // manager.py resolves the classifier to run from "model"
+    if let Some(ml) = ml {
+        let ml_config = PyDict::new(py);
+        ml_config
+            .set_item("model", &ml.model)
+            .map_err(|e| format!("Failed to set model: {}", e))?;
+
+        let node_dict = PyDict::new(py);
+        node_dict
+            .set_item("type", "ml")
+            .map_err(|e| format!("Failed to set node type: {}", e))?;
+        node_dict
+            .set_item("config", ml_config)
+            .map_err(|e| format!("Failed to set node config: {}", e))?;
+        nodes_list
+            .append(node_dict)
+            .map_err(|e| format!("Failed to append ml node: {}", e))?;
+    }
+
     let pipeline_dict = PyDict::new(py);
     pipeline_dict
         .set_item("nodes", nodes_list)
@@ -134,8 +161,8 @@ fn build_python_pipeline_dict<'py>(
 }
 
 // Transposes EEG signals from (n_channels, n_samples) to (n_samples, n_channels).
-// Rust stores signals as Vec<Vec<f64>> shaped (4, n_samples) — channels first.
-// manager.py expects (n_samples, 4) — samples first.
+// Rust stores signals as Vec<Vec<f64>> shaped (n_channels, n_samples) — channels first.
+// manager.py expects (n_samples, n_channels) — samples first.
 pub fn transpose_signals(signals: &[Vec<f64>]) -> Vec<Vec<f64>> {
     if signals.is_empty() || signals[0].is_empty() {
         return Vec::new();
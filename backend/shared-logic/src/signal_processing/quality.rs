@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+// Per-channel contact quality of each window, computed on the raw samples (before the
+// bandpass, which would hide line noise) so the frontend can show a contact indicator.
+
+// Thresholds of the quality rating, set with a "signalQuality" pipeline node.
+// Amplitudes are in microvolts; the line noise ratio is the share of the window's
+// variance at the line frequency.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct QualityConfig {
+    // 60 Hz in North America, 50 Hz in most other places
+    pub line_frequency: f64,
+    // a channel varying less than this isn't picking anything up
+    pub flatline_std: f64,
+    // share of samples stuck at the window's extremes (or past rail_value) that means clipping
+    pub max_saturation: f64,
+    // absolute value the amplifier rails at, when known
+    pub rail_value: Option<f64>,
+    pub fair_std: f64,
+    pub poor_std: f64,
+    pub fair_line_noise: f64,
+    pub poor_line_noise: f64,
+}
+
+impl Default for QualityConfig {
+    fn default() -> Self {
+        Self {
+            line_frequency: 60.0,
+            flatline_std: 0.5,
+            max_saturation: 0.05,
+            rail_value: None,
+            fair_std: 50.0,
+            poor_std: 100.0,
+            fair_line_noise: 0.25,
+            poor_line_noise: 0.5,
+        }
+    }
+}
+
+impl QualityConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.line_frequency.is_finite() && self.line_frequency > 0.0) {
+            return Err(format!(
+                "line_frequency must be positive, got {}",
+                self.line_frequency
+            ));
+        }
+        if self.fair_std > self.poor_std {
+            return Err("fair_std must not be above poor_std".to_string());
+        }
+        if self.fair_line_noise > self.poor_line_noise {
+            return Err("fair_line_noise must not be above poor_line_noise".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum QualityRating {
+    Good,
+    Fair,
+    Poor,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChannelQuality {
+    pub rating: QualityRating,
+    pub flatline: bool,
+    // share of samples at the rails
+    pub saturation: f64,
+    // standard deviation in microvolts
+    pub std: f64,
+    // share of the variance at the line frequency
+    pub line_noise: f64,
+}
+
+// Rates every channel of a window. An empty window rates nothing.
+pub fn assess_window(
+    signals: &[Vec<f64>],
+    sample_rate: f64,
+    config: &QualityConfig,
+) -> Vec<ChannelQuality> {
+    signals
+        .iter()
+        .filter(|channel| !channel.is_empty())
+        .map(|channel| assess_channel(channel, sample_rate, config))
+        .collect()
+}
+
+fn assess_channel(samples: &[f64], sample_rate: f64, config: &QualityConfig) -> ChannelQuality {
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    let variance = samples.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    let std = variance.sqrt();
+    let flatline = std < config.flatline_std;
+
+    // a clipped signal sits at its extremes for many samples, a clean one touches each once
+    let (min, max) = samples
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| {
+            (lo.min(v), hi.max(v))
+        });
+    let railed = samples
+        .iter()
+        .filter(|&&v| {
+            let at_rail = config.rail_value.is_some_and(|rail| v.abs() >= rail);
+            at_rail || (!flatline && (v == min || v == max))
+        })
+        .count();
+    // the two samples the min and max are set by are fine
+    let saturation = if config.rail_value.is_some() || flatline {
+        railed as f64 / n
+    } else {
+        railed.saturating_sub(2) as f64 / n
+    };
+
+    let line_noise = if variance > 0.0 && config.line_frequency < sample_rate / 2.0 {
+        let amplitude = amplitude_at(samples, mean, config.line_frequency, sample_rate);
+        // a sinusoid of amplitude A has variance A² / 2
+        (amplitude * amplitude / 2.0 / variance).min(1.0)
+    } else {
+        0.0
+    };
+
+    let rating = if flatline
+        || saturation >= config.max_saturation
+        || std >= config.poor_std
+        || line_noise >= config.poor_line_noise
+    {
+        QualityRating::Poor
+    } else if std >= config.fair_std || line_noise >= config.fair_line_noise {
+        QualityRating::Fair
+    } else {
+        QualityRating::Good
+    };
+
+    ChannelQuality {
+        rating,
+        flatline,
+        saturation,
+        std,
+        line_noise,
+    }
+}
+
+// Amplitude of the `freq` component of the samples (a single DFT bin, mean removed)
+fn amplitude_at(samples: &[f64], mean: f64, freq: f64, sample_rate: f64) -> f64 {
+    let step = 2.0 * PI * freq / sample_rate;
+    let (re, im) = samples
+        .iter()
+        .enumerate()
+        .fold((0.0, 0.0), |(re, im), (i, v)| {
+            let angle = step * i as f64;
+            (re + (v - mean) * angle.cos(), im - (v - mean) * angle.sin())
+        });
+    2.0 * (re * re + im * im).sqrt() / samples.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assess_window_rates_contact_problems() {
+        let rate = 256.0;
+        let wave = |i: usize, freq: f64, amplitude: f64| {
+            amplitude * (2.0 * PI * freq * i as f64 / rate).sin()
+        };
+        let good: Vec<f64> = (0..256)
+            .map(|i| wave(i, 10.0, 10.0) + wave(i, 23.0, 5.0))
+            .collect();
+        let flat = vec![800.0; 256];
+        let clipped: Vec<f64> = good.iter().map(|v| v.clamp(-6.0, 6.0)).collect();
+        let mains: Vec<f64> = (0..256)
+            .map(|i| wave(i, 10.0, 5.0) + wave(i, 60.0, 20.0))
+            .collect();
+        let noisy: Vec<f64> = good.iter().map(|v| v * 8.0).collect();
+
+        let quality = assess_window(
+            &[good, flat, clipped, mains, noisy],
+            rate,
+            &QualityConfig::default(),
+        );
+        let ratings: Vec<_> = quality.iter().map(|q| q.rating).collect();
+        assert_eq!(
+            ratings,
+            vec![
+                QualityRating::Good,
+                QualityRating::Poor,
+                QualityRating::Poor,
+                QualityRating::Poor,
+                QualityRating::Fair,
+            ]
+        );
+        assert!(quality[1].flatline);
+        assert!(quality[2].saturation > 0.1);
+        assert!(quality[3].line_noise > 0.9);
+        assert!(quality[0].line_noise < 0.01);
+    }
+}
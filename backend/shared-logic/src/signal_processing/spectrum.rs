@@ -0,0 +1,398 @@
+use std::f64::consts::PI;
+
+use chrono::{DateTime, Utc};
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use serde::{Deserialize, Serialize};
+
+use crate::formats::estimate_sample_rate;
+use crate::models::EegDataRow;
+
+// Spectral features of the EEG: the power in the classic frequency bands of each live window,
+// in microvolts² (absolute) and as a share of the total, and the Welch power spectral
+// density of stored session data.
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Band {
+    pub name: String,
+    // Hz, the band covers low <= f < high
+    pub low: f64,
+    pub high: f64,
+}
+
+impl Band {
+    fn new(name: &str, low: f64, high: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            low,
+            high,
+        }
+    }
+}
+
+// Band edges of a "bandPower" pipeline node. Band power is only computed with one.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct BandPowerConfig {
+    pub bands: Vec<Band>,
+}
+
+impl Default for BandPowerConfig {
+    fn default() -> Self {
+        Self {
+            bands: vec![
+                Band::new("delta", 1.0, 4.0),
+                Band::new("theta", 4.0, 8.0),
+                Band::new("alpha", 8.0, 13.0),
+                Band::new("beta", 13.0, 30.0),
+                Band::new("gamma", 30.0, 45.0),
+            ],
+        }
+    }
+}
+
+impl BandPowerConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.bands.is_empty() {
+            return Err("band power needs at least one band".to_string());
+        }
+        for band in &self.bands {
+            if !(band.low.is_finite() && band.high.is_finite() && band.low >= 0.0) {
+                return Err(format!("band {} has invalid edges", band.name));
+            }
+            if band.low >= band.high {
+                return Err(format!(
+                    "band {} must start below its end ({} >= {})",
+                    band.name, band.low, band.high
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Lowest and highest edge of the bands, the range relative power is taken over
+    fn span(&self) -> (f64, f64) {
+        self.bands
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), b| {
+                (lo.min(b.low), hi.max(b.high))
+            })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BandPower {
+    pub band: String,
+    // microvolts²
+    pub absolute: f64,
+    // share of the power between the lowest and highest band edge
+    pub relative: f64,
+}
+
+// Band power of every channel of a window (outer Vec per channel, inner per band).
+pub fn band_power(
+    signals: &[Vec<f64>],
+    sample_rate: f64,
+    config: &BandPowerConfig,
+) -> Vec<Vec<BandPower>> {
+    let mut planner = FftPlanner::new();
+    let (span_low, span_high) = config.span();
+    signals
+        .iter()
+        .filter(|channel| channel.len() > 1)
+        .map(|channel| {
+            let window = WindowFunction::Hann.coefficients(channel.len());
+            let psd = periodogram(&mut planner, channel, sample_rate, &window);
+            let resolution = sample_rate / channel.len() as f64;
+            let total = integrate(&psd, resolution, span_low, span_high);
+            config
+                .bands
+                .iter()
+                .map(|band| {
+                    let absolute = integrate(&psd, resolution, band.low, band.high);
+                    BandPower {
+                        band: band.name.clone(),
+                        absolute,
+                        relative: if total > 0.0 { absolute / total } else { 0.0 },
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Taper applied to each segment before its FFT, to limit leakage between frequencies
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowFunction {
+    #[default]
+    Hann,
+    Hamming,
+    // no taper, "boxcar" in scipy
+    #[serde(alias = "boxcar")]
+    Rectangular,
+}
+
+impl WindowFunction {
+    // The window over n samples, periodic like scipy's spectral windows
+    pub fn coefficients(self, n: usize) -> Vec<f64> {
+        (0..n)
+            .map(|i| {
+                let phase = 2.0 * PI * i as f64 / n as f64;
+                match self {
+                    WindowFunction::Hann => 0.5 - 0.5 * phase.cos(),
+                    WindowFunction::Hamming => 0.54 - 0.46 * phase.cos(),
+                    WindowFunction::Rectangular => 1.0,
+                }
+            })
+            .collect()
+    }
+}
+
+// Settings of a Welch PSD estimate, scipy.signal.welch's nperseg, noverlap and window
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct WelchConfig {
+    // samples per segment, 256 when not set (or the whole signal when it is shorter)
+    pub segment_len: Option<usize>,
+    // samples shared by consecutive segments, half a segment when not set
+    pub overlap: Option<usize>,
+    #[serde(default)]
+    pub window: WindowFunction,
+}
+
+const DEFAULT_SEGMENT_LEN: usize = 256;
+
+impl WelchConfig {
+    // Segment length and step for a signal of n samples
+    fn segments(&self, n: usize) -> Result<(usize, usize), String> {
+        let segment_len = self.segment_len.unwrap_or(DEFAULT_SEGMENT_LEN).min(n);
+        if segment_len < 2 {
+            return Err("segments need at least 2 samples".to_string());
+        }
+        let overlap = self.overlap.unwrap_or(segment_len / 2);
+        if overlap >= segment_len {
+            return Err(format!(
+                "overlap ({}) must be smaller than the segment length ({})",
+                overlap, segment_len
+            ));
+        }
+        Ok((segment_len, segment_len - overlap))
+    }
+}
+
+// Welch PSD of a stretch of a session, one power array per requested channel
+#[derive(Serialize, Debug, Clone)]
+pub struct PowerSpectrum {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    // estimated from the timestamps
+    pub sample_rate: f64,
+    // Hz, the same for every channel
+    pub frequencies: Vec<f64>,
+    pub channels: Vec<ChannelSpectrum>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ChannelSpectrum {
+    pub channel: usize,
+    // microvolts²/Hz
+    pub power: Vec<f64>,
+}
+
+// Welch PSD of the stored rows, for the given channel indices (all when None).
+// Samples are treated as evenly spaced, gaps in the recording are not skipped.
+pub fn rows_power_spectrum(
+    rows: &[EegDataRow],
+    channels: Option<&[usize]>,
+    config: &WelchConfig,
+) -> Result<PowerSpectrum, String> {
+    let times: Vec<DateTime<Utc>> = rows.iter().map(|row| row.time).collect();
+    let sample_rate = estimate_sample_rate(&times)
+        .ok_or_else(|| "Need at least 2 samples for a PSD".to_string())?;
+    let n_channels = rows.iter().map(|row| row.channels.len()).min().unwrap_or(0);
+    let channels: Vec<usize> = match channels {
+        Some(channels) => channels.to_vec(),
+        None => (0..n_channels).collect(),
+    };
+    if let Some(&ch) = channels.iter().find(|&&ch| ch >= n_channels) {
+        return Err(format!(
+            "channel {} is out of range, the session has {} channels",
+            ch, n_channels
+        ));
+    }
+
+    let mut planner = FftPlanner::new();
+    let mut frequencies = Vec::new();
+    let mut spectra = Vec::with_capacity(channels.len());
+    for &channel in &channels {
+        let samples: Vec<f64> = rows.iter().map(|row| row.channels[channel]).collect();
+        let (freqs, power) = welch(&mut planner, &samples, sample_rate, config)?;
+        frequencies = freqs;
+        spectra.push(ChannelSpectrum { channel, power });
+    }
+    Ok(PowerSpectrum {
+        start: times[0],
+        end: times[times.len() - 1],
+        sample_rate,
+        frequencies,
+        channels: spectra,
+    })
+}
+
+// Welch's method: the average periodogram of overlapping tapered segments.
+// Returns the frequencies and the one-sided PSD at each of them.
+pub fn welch(
+    planner: &mut FftPlanner<f64>,
+    samples: &[f64],
+    sample_rate: f64,
+    config: &WelchConfig,
+) -> Result<(Vec<f64>, Vec<f64>), String> {
+    let (segment_len, step) = config.segments(samples.len())?;
+    let window = config.window.coefficients(segment_len);
+
+    let mut power = vec![0.0; segment_len / 2 + 1];
+    let mut n_segments = 0;
+    for start in (0..=samples.len() - segment_len).step_by(step) {
+        let segment = periodogram(
+            planner,
+            &samples[start..start + segment_len],
+            sample_rate,
+            &window,
+        );
+        for (total, p) in power.iter_mut().zip(segment) {
+            *total += p;
+        }
+        n_segments += 1;
+    }
+    for p in &mut power {
+        *p /= n_segments as f64;
+    }
+    let frequencies = (0..power.len())
+        .map(|k| k as f64 * sample_rate / segment_len as f64)
+        .collect();
+    Ok((frequencies, power))
+}
+
+// One-sided power spectral density of the samples (mean removed, tapered by `window`) in
+// units²/Hz, at the frequencies k * sample_rate / n for k in 0..=n/2.
+pub fn periodogram(
+    planner: &mut FftPlanner<f64>,
+    samples: &[f64],
+    sample_rate: f64,
+    window: &[f64],
+) -> Vec<f64> {
+    let n = samples.len();
+    let mean = samples.iter().sum::<f64>() / n as f64;
+    let mut buffer: Vec<Complex<f64>> = samples
+        .iter()
+        .zip(window)
+        .map(|(v, w)| Complex::new((v - mean) * w, 0.0))
+        .collect();
+    planner.plan_fft_forward(n).process(&mut buffer);
+
+    // scaled so the PSD integrates to the variance of the (untapered) signal
+    let scale = 1.0 / (sample_rate * window.iter().map(|w| w * w).sum::<f64>());
+    (0..=n / 2)
+        .map(|k| {
+            let power = buffer[k].norm_sqr() * scale;
+            // the negative frequencies fold onto the positive ones, except DC and Nyquist
+            if k == 0 || 2 * k == n {
+                power
+            } else {
+                2.0 * power
+            }
+        })
+        .collect()
+}
+
+// Power between low and high (Hz) of a PSD with bins `resolution` Hz apart
+fn integrate(psd: &[f64], resolution: f64, low: f64, high: f64) -> f64 {
+    psd.iter()
+        .enumerate()
+        .filter(|(k, _)| {
+            let freq = *k as f64 * resolution;
+            freq >= low && freq < high
+        })
+        .map(|(_, p)| p * resolution)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_band_power_finds_the_dominant_rhythm() {
+        let rate = 256.0;
+        let wave = |i: usize, freq: f64, amplitude: f64| {
+            amplitude * (2.0 * PI * freq * i as f64 / rate).sin()
+        };
+        let alpha: Vec<f64> = (0..512).map(|i| 5.0 + wave(i, 10.0, 10.0)).collect();
+        let beta: Vec<f64> = (0..512)
+            .map(|i| wave(i, 20.0, 4.0) + wave(i, 6.0, 1.0))
+            .collect();
+
+        let power = band_power(&[alpha, beta], rate, &BandPowerConfig::default());
+        let get =
+            |ch: usize, band: &str| power[ch].iter().find(|p| p.band == band).cloned().unwrap();
+        // a sinusoid of amplitude A has power A² / 2, the offset isn't counted
+        assert!((get(0, "alpha").absolute - 50.0).abs() < 1.0);
+        assert!(get(0, "alpha").relative > 0.99);
+        assert!(get(0, "delta").absolute < 0.1);
+        assert!((get(1, "beta").absolute - 8.0).abs() < 0.2);
+        assert!((get(1, "theta").relative - 0.5 / 8.5).abs() < 0.01);
+        let sum: f64 = power[1].iter().map(|p| p.relative).sum();
+        assert!((sum - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_welch_psd_of_stored_rows() {
+        let rate = 250.0;
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let rows: Vec<EegDataRow> = (0..2500)
+            .map(|i| {
+                let t = i as f64 / rate;
+                EegDataRow {
+                    time: start + chrono::Duration::milliseconds(4 * i),
+                    channels: vec![
+                        20.0 * (2.0 * PI * 12.5 * t).sin(),
+                        3.0 * (2.0 * PI * 50.0 * t).sin() + 100.0,
+                    ],
+                }
+            })
+            .collect();
+
+        for window in [WindowFunction::Hann, WindowFunction::Hamming] {
+            let config = WelchConfig {
+                segment_len: Some(500),
+                overlap: None,
+                window,
+            };
+            let spectrum = rows_power_spectrum(&rows, Some(&[1, 0]), &config).unwrap();
+            assert!((spectrum.sample_rate - rate).abs() < 1e-9);
+            assert_eq!(spectrum.frequencies.len(), 251);
+            assert_eq!(spectrum.frequencies[1], 0.5);
+            assert_eq!(spectrum.channels[0].channel, 1);
+
+            let peak = |power: &[f64]| {
+                (0..power.len())
+                    .max_by(|&a, &b| power[a].total_cmp(&power[b]))
+                    .map(|k| spectrum.frequencies[k])
+                    .unwrap()
+            };
+            assert_eq!(peak(&spectrum.channels[0].power), 50.0);
+            assert_eq!(peak(&spectrum.channels[1].power), 12.5);
+            // the PSD integrates to the variance, A² / 2 for a sinusoid
+            let total: f64 = spectrum.channels[1].power.iter().sum::<f64>() * 0.5;
+            assert!((total - 200.0).abs() < 4.0, "{:?}: {}", window, total);
+        }
+
+        let bad = WelchConfig {
+            segment_len: Some(100),
+            overlap: Some(100),
+            window: WindowFunction::Rectangular,
+        };
+        assert!(rows_power_spectrum(&rows, None, &bad).is_err());
+        assert!(rows_power_spectrum(&rows, Some(&[2]), &WelchConfig::default()).is_err());
+    }
+}
@@ -0,0 +1,43 @@
+# Writes filter_reference.csv, the reference the bandpass tests in filters.rs compare against.
+# Needs numpy, mne and scipy (the versions signalProcessing.py runs with):
+#   python make_filter_reference.py
+# Columns: the two input channels, then each channel after the default FIR bandpass (MNE),
+# then after the default IIR bandpass (scipy), all for one 256-sample window at 256 Hz.
+import csv
+import os
+import sys
+
+import numpy as np
+
+sys.path.insert(0, os.path.join(os.path.dirname(__file__), ".."))
+from signalProcessing import signalProcessing  # noqa: E402
+
+SFREQ = 256.0
+N_SAMPLES = 256
+
+
+def main():
+    t = np.arange(N_SAMPLES) / SFREQ
+    # slow drift, alpha, line noise and an offset: in band and out of band on both sides
+    data = np.array(
+        [
+            20.0 + 15.0 * np.sin(2 * np.pi * 0.3 * t) + 10.0 * np.sin(2 * np.pi * 10.0 * t)
+            + 5.0 * np.sin(2 * np.pi * 60.0 * t),
+            -8.0 + 12.0 * np.cos(2 * np.pi * 0.5 * t) + 6.0 * np.sin(2 * np.pi * 22.0 * t)
+            + 4.0 * np.sin(2 * np.pi * 90.0 * t),
+        ]
+    )
+    fir = signalProcessing.fir_bandpass_filter(data, SFREQ)
+    iir = signalProcessing.iir_bandpass_filter(data, SFREQ)
+
+    path = os.path.join(os.path.dirname(__file__), "filter_reference.csv")
+    with open(path, "w", newline="") as f:
+        writer = csv.writer(f)
+        writer.writerow(["input0", "input1", "fir0", "fir1", "iir0", "iir1"])
+        for i in range(N_SAMPLES):
+            row = [data[0, i], data[1, i], fir[0, i], fir[1, i], iir[0, i], iir[1, i]]
+            writer.writerow([repr(float(v)) for v in row])
+
+
+if __name__ == "__main__":
+    main()
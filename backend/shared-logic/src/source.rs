@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::lsl::{LslSource, StreamSelector, DEFAULT_LOST_AFTER_SECS};
+use crate::mockeeg::MockConfig;
+use crate::models::ClockOffset;
+use crate::replay::{ReplayConfig, ReplaySource};
+
+// Most samples taken out of a source in one pull (half a second of data at 2 kHz)
+pub const MAX_PULL_SAMPLES: usize = 1024;
+
+// Where a session's samples come from. The collection loop (lsl::run_eeg_collection) pulls
+// from it in chunks and does the windowing, so a source only has to deliver timestamped samples.
+pub trait EegSource {
+    fn n_channels(&self) -> usize;
+
+    // Pulls everything the source has queued (up to MAX_PULL_SAMPLES) into the buffer.
+    // Waits up to `timeout` seconds for the first sample. Returns the number of samples
+    // pulled, 0 if nothing arrived within the timeout.
+    fn pull_chunk(&mut self, buffer: &mut PullBuffer, timeout: f64) -> Result<usize, String>;
+
+    // Seconds to add to the source's timestamps to get Unix time. Called before every pull,
+    // so sources with their own clock can re-measure it here.
+    fn clock_offset(&mut self) -> f64 {
+        0.0
+    }
+
+    // Clock offset measurements made during the session, stored with it at the end.
+    fn take_clock_history(&mut self) -> Vec<ClockOffset> {
+        Vec::new()
+    }
+
+    // How long the source may go without samples before it is considered lost.
+    fn lost_after(&self) -> Duration {
+        Duration::from_secs_f64(DEFAULT_LOST_AFTER_SECS)
+    }
+
+    // Tries to get a lost source back. Returns false if the session was cancelled first.
+    // Sources that can't be lost just keep going.
+    fn reconnect(&mut self, cancel_token: &CancellationToken) -> bool {
+        !cancel_token.is_cancelled()
+    }
+
+    // True once a source with an end (a replay) has handed out all its samples,
+    // which ends the session like a cancel.
+    fn finished(&self) -> bool {
+        false
+    }
+}
+
+// Picks the source of a session, sent in the WebSocket init message as
+// {"type": "lsl", "config": {...}}.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", content = "config")]
+pub enum SourceConfig {
+    #[serde(rename = "lsl")]
+    Lsl(StreamSelector),
+
+    // plays back a stored session instead of recording a new one
+    #[serde(rename = "replay")]
+    Replay(ReplayConfig),
+
+    // a simulated headset, published on LSL by the server and recorded from there
+    #[serde(rename = "mock")]
+    Mock(MockConfig),
+}
+
+impl Default for SourceConfig {
+    fn default() -> Self {
+        SourceConfig::Lsl(StreamSelector::default())
+    }
+}
+
+impl SourceConfig {
+    // Opens the source. Blocks until it is found (or its timeout passes).
+    pub fn connect(self) -> Result<Box<dyn EegSource>, String> {
+        match self {
+            SourceConfig::Lsl(selector) => Ok(Box::new(LslSource::connect(selector)?)),
+            SourceConfig::Replay(config) => Ok(Box::new(ReplaySource::connect(config)?)),
+            SourceConfig::Mock(config) => {
+                config.validate()?;
+                Ok(Box::new(LslSource::connect(config.selector())?))
+            }
+        }
+    }
+
+    // Whether the session's samples are new and should be written to eeg_data.
+    // A replay's samples are already stored.
+    pub fn records(&self) -> bool {
+        !matches!(self, SourceConfig::Replay(_))
+    }
+}
+
+// Preallocated buffers samples are pulled into, reused for every pull.
+pub struct PullBuffer {
+    n_channels: usize,
+    // sample-major, sample i is data[i * n_channels..(i + 1) * n_channels]
+    data: Vec<f64>,
+    timestamps: Vec<f64>,
+}
+
+impl PullBuffer {
+    pub fn new(n_channels: usize) -> Self {
+        Self {
+            n_channels,
+            data: Vec::with_capacity(MAX_PULL_SAMPLES * n_channels),
+            timestamps: Vec::with_capacity(MAX_PULL_SAMPLES),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.timestamps.clear();
+    }
+
+    // Appends one sample, as f32 from LSL or f64 from the database. Values past n_channels
+    // are ignored, missing ones are zero.
+    pub fn push<T: Copy + Into<f64>>(&mut self, timestamp: f64, sample: &[T]) {
+        let n = sample.len().min(self.n_channels);
+        self.data.extend(sample[..n].iter().map(|&v| v.into()));
+        self.data.resize(self.data.len() + self.n_channels - n, 0.0);
+        self.timestamps.push(timestamp);
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.timestamps.len() >= MAX_PULL_SAMPLES
+    }
+
+    pub fn len(&self) -> usize {
+        self.timestamps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timestamps.is_empty()
+    }
+
+    pub fn n_channels(&self) -> usize {
+        self.n_channels
+    }
+
+    pub fn timestamps(&self) -> &[f64] {
+        &self.timestamps
+    }
+
+    // Value of channel `channel` in sample `index`.
+    pub fn value(&self, index: usize, channel: usize) -> f64 {
+        self.data[index * self.n_channels + channel]
+    }
+}
@@ -3,12 +3,18 @@ use futures_util::stream::SplitSink;
 use futures_util::{SinkExt, StreamExt};
 use log::{error, info};
 use serde::Deserialize;
+use serde_json::json;
 use shared_logic::bc::start_broadcast;
 use shared_logic::db::initialize_connection;
-use shared_logic::pipeline::{Node, Pipeline};
+use shared_logic::lsl::StreamSelector;
+use shared_logic::markers::MarkerConfig;
+use shared_logic::mockeeg::MockConfig;
+use shared_logic::outlet::OutletConfig;
+use shared_logic::pipeline::{Node, Pipeline, WindowConfig};
+use shared_logic::source::SourceConfig;
 use std::sync::Arc;
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::Mutex;
+use tokio::sync::{watch, Mutex};
 use tokio_tungstenite::{accept_async, tungstenite::Message, WebSocketStream};
 use tokio_util::sync::CancellationToken;
 
@@ -16,6 +22,35 @@ use tokio_util::sync::CancellationToken;
 struct WebSocketInitMessage {
     session_id: String,
     nodes: Vec<Node>,
+    // where the samples come from, defaults to the server's EEG_SOURCE (see default_source)
+    #[serde(default)]
+    source: Option<SourceConfig>,
+    // which LSL stream to record, defaults to the first type='EEG' stream.
+    // Used without `source`, it asks for a headset over LSL.
+    #[serde(default)]
+    stream: Option<StreamSelector>,
+    // LSL outlets to republish the processed signal and ML results on, none by default
+    #[serde(default)]
+    outlets: OutletConfig,
+    // an LSL marker stream whose events are stored as time labels while recording
+    #[serde(default)]
+    markers: Option<MarkerConfig>,
+}
+
+// JSON messages the client can send once the session is running
+#[derive(Deserialize)]
+#[serde(tag = "type", content = "config")]
+enum ClientMessage {
+    #[serde(rename = "reconfigureWindow")]
+    ReconfigureWindow(WindowConfig),
+
+    // replaces the whole pipeline
+    #[serde(rename = "updatePipeline")]
+    UpdatePipeline(Pipeline),
+
+    // replaces the node of the same type, or adds it
+    #[serde(rename = "updateNode")]
+    UpdateNode(Node),
 }
 
 #[tokio::main]
@@ -56,6 +91,25 @@ pub async fn run_server() {
     }
 }
 
+// default_source picks the source of sessions whose init message doesn't name one, from the
+// EEG_SOURCE environment variable: "lsl" records the headset matching the `stream` selector,
+// "mock" a simulated one, so demo and lab deployments run the same binary. Without
+// EEG_SOURCE, a client that sends a `stream` selector gets LSL and the others the mock.
+// A selector the server can't honour is an error rather than a session of mock data.
+fn default_source(stream: Option<StreamSelector>) -> Result<SourceConfig, String> {
+    match (std::env::var("EEG_SOURCE").as_deref(), stream) {
+        (Ok("lsl"), stream) | (Err(_), stream @ Some(_)) => {
+            Ok(SourceConfig::Lsl(stream.unwrap_or_default()))
+        }
+        (Ok("mock"), None) | (Err(_), None) => Ok(SourceConfig::Mock(MockConfig::default())),
+        (Ok("mock"), Some(_)) => Err(
+            "This server only runs the mock headset (EEG_SOURCE=mock), it can't record a stream"
+                .to_string(),
+        ),
+        (Ok(other), _) => Err(format!("Unknown EEG_SOURCE \"{}\" on the server", other)),
+    }
+}
+
 // handle_ws accepts a Tcp connection and upgrades it to a WebSocket connection.
 // If successfully upgraded, print "Client connected", and call another function to do stuff with it.
 // If not successfully, log the error to standard error.
@@ -90,19 +144,34 @@ async fn handle_connection(ws_stream: WebSocketStream<TcpStream>) {
     let cancel_clone = cancel_token.clone();
 
     // Listen for the first non-null text message — this is the pipeline init message.
-    let init_message = loop {
+    let (init_message, source) = loop {
         match read.next().await {
             Some(Ok(msg)) if msg.is_text() => {
                 let text = match msg.to_text() {
                     Ok(t) if !t.is_empty() => t,
                     _ => continue,
                 };
-                match serde_json::from_str::<WebSocketInitMessage>(text) {
-                    Ok(init) => break init,
+                let mut init = match serde_json::from_str::<WebSocketInitMessage>(text) {
+                    Ok(init) => init,
                     Err(e) => {
                         error!("Failed to parse init message JSON: {}", e);
                         continue;
                     }
+                };
+                let source = match init.source.take() {
+                    Some(source) => Ok(source),
+                    None => default_source(init.stream.take()),
+                };
+                match source {
+                    Ok(source) => break (init, source),
+                    Err(e) => {
+                        error!("Rejected init message: {}", e);
+                        let reply = json!({ "error": e });
+                        let mut write_guard = write.lock().await;
+                        if let Err(e) = write_guard.send(Message::Text(reply.to_string())).await {
+                            error!("Failed to send message: {}", e);
+                        }
+                    }
                 }
             }
             Some(Ok(_)) => continue, // ping, binary, etc — keep waiting
@@ -121,11 +190,25 @@ async fn handle_connection(ws_stream: WebSocketStream<TcpStream>) {
     let pipeline = Pipeline {
         nodes: init_message.nodes,
     };
+    let outlets = init_message.outlets;
+    let markers = init_message.markers;
     info!("Received pipeline with {} nodes", pipeline.nodes.len());
 
+    // the pipeline can be changed during the session through this channel
+    let (pipeline_tx, pipeline_rx) = watch::channel(pipeline);
+
     // spawns the broadcast task
     let mut broadcast = Some(tokio::spawn(async move {
-        start_broadcast(write_clone, cancel_clone, pipeline, session_id).await;
+        start_broadcast(
+            write_clone,
+            cancel_clone,
+            pipeline_rx,
+            source,
+            outlets,
+            markers,
+            session_id,
+        )
+        .await;
     }));
 
     while let Some(msg) = read.next().await {
@@ -136,6 +219,10 @@ async fn handle_connection(ws_stream: WebSocketStream<TcpStream>) {
                     handle_prep_close(&mut broadcast, &cancel_token, &write).await;
                     break;
                 }
+                match serde_json::from_str::<ClientMessage>(text) {
+                    Ok(message) => handle_pipeline_update(message, &pipeline_tx, &write).await,
+                    Err(e) => error!("Unrecognized client message: {}", e),
+                }
             }
             Ok(Message::Close(frame)) => {
                 let mut w = write.lock().await;
@@ -152,6 +239,45 @@ async fn handle_connection(ws_stream: WebSocketStream<TcpStream>) {
     info!("Client disconnected.");
 }
 
+// handle_pipeline_update applies a client message to the current pipeline, validates the result,
+// hands it to the collection loop through the watch channel (which switches to it at the next
+// window) and tells the client whether it was accepted.
+async fn handle_pipeline_update(
+    message: ClientMessage,
+    pipeline_tx: &watch::Sender<Pipeline>,
+    write: &Arc<Mutex<SplitSink<WebSocketStream<TcpStream>, Message>>>,
+) {
+    let mut pipeline = pipeline_tx.borrow().clone();
+    let window_only = matches!(message, ClientMessage::ReconfigureWindow(_));
+    match message {
+        ClientMessage::ReconfigureWindow(config) => pipeline.set_node(Node::Window(config)),
+        ClientMessage::UpdatePipeline(new_pipeline) => pipeline = new_pipeline,
+        ClientMessage::UpdateNode(node) => pipeline.set_node(node),
+    }
+
+    let reply = match pipeline.validate() {
+        Ok(()) => {
+            info!("Updating pipeline ({} nodes)", pipeline.nodes.len());
+            let reply = if window_only {
+                json!({ "type": "windowReconfigured", "config": pipeline.window_config() })
+            } else {
+                json!({ "type": "pipelineUpdated", "config": pipeline })
+            };
+            pipeline_tx.send_replace(pipeline);
+            reply
+        }
+        Err(e) => {
+            error!("Rejected pipeline update: {}", e);
+            json!({ "error": format!("Invalid pipeline: {}", e) })
+        }
+    };
+
+    let mut write_guard = write.lock().await;
+    if let Err(e) = write_guard.send(Message::Text(reply.to_string())).await {
+        error!("Failed to send message: {}", e);
+    }
+}
+
 // handle_prep_close uses the cancel_token to stop the broadcast sender task, and sends a "prep close complete" message to the client
 async fn handle_prep_close(
     broadcast_task: &mut Option<tokio::task::JoinHandle<()>>,
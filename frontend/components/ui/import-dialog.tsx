@@ -9,7 +9,7 @@ import {
     DialogTitle,
 } from '@/components/ui/dialog';
 import { useNotifications } from '@/components/notifications';
-import { importEEGData } from '@/lib/eeg-api';
+import { ImportFormat, importEEGData, importEEGFile } from '@/lib/eeg-api';
 import { Folder } from 'lucide-react';
 import { EnterIcon, InfoCircledIcon } from '@radix-ui/react-icons';
 import {
@@ -18,6 +18,9 @@ import {
     PopoverTrigger,
 } from '@/components/ui/popover';
 
+// formats uploaded as raw bytes, recognised by file extension
+const BINARY_FORMATS: ImportFormat[] = ['xdf', 'edf'];
+
 type ImportDialogProps = {
     open: boolean;
     sessionId: number | null;
@@ -53,18 +56,28 @@ export default function ImportDialog({
             return;
         }
 
-        if (file.type !== 'text/csv' && !file.name.endsWith('.csv')) {
+        const extension = file.name.toLowerCase().split('.').pop();
+        const binaryFormat = BINARY_FORMATS.find((f) => f === extension);
+        if (
+            !binaryFormat &&
+            file.type !== 'text/csv' &&
+            !file.name.endsWith('.csv')
+        ) {
             notifications.error({
                 title: 'Invalid file type',
-                description: 'Please select a CSV file.',
+                description: 'Please select a CSV, XDF or EDF file.',
             });
             return;
         }
 
         setIsImporting(true);
         try {
-            const csvText = await file.text();
-            await importEEGData(sessionId, csvText);
+            if (binaryFormat) {
+                await importEEGFile(sessionId, file, binaryFormat);
+            } else {
+                const csvText = await file.text();
+                await importEEGData(sessionId, csvText);
+            }
             notifications.success({
                 title: 'EEG data imported successfully',
                 description: `${file.name} has been loaded into session ${sessionId}.`,
@@ -122,7 +135,7 @@ export default function ImportDialog({
                         Import Data
                     </DialogTitle>
                     <DialogDescription className="text-gray-500 text-sm flex items-center gap-1.5">
-                        Only CSV files are accepted.
+                        CSV, XDF and EDF files are accepted.
                         <Popover>
                             <PopoverTrigger asChild>
                                 <button
@@ -186,7 +199,7 @@ export default function ImportDialog({
                         <p className="text-sm font-medium text-gray-900 mb-1">
                             {isImporting
                                 ? 'Importing...'
-                                : 'Drag CSV file here'}
+                                : 'Drag CSV, XDF or EDF file here'}
                         </p>
                         {!isImporting && (
                             <p className="text-sm text-gray-500">
@@ -205,7 +218,7 @@ export default function ImportDialog({
                         <input
                             ref={fileInputRef}
                             type="file"
-                            accept=".csv,text/csv"
+                            accept=".csv,text/csv,.xdf,.edf"
                             onChange={handleFileChange}
                             disabled={isImporting}
                             className="hidden"
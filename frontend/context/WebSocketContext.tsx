@@ -9,7 +9,7 @@ import React, {
     ReactNode,
 } from 'react';
 import { useGlobalContext } from './GlobalContext';
-import { PipelinePayload } from '@/lib/pipeline';
+import { PipelineNode, PipelinePayload, WindowConfig } from '@/lib/pipeline';
 
 export type DataPoint = {
     time: string;
@@ -20,11 +20,47 @@ export type DataPoint = {
     signal4: number;
 };
 
+// Contact quality of one channel for the latest window, rated on the raw signal
+export type ChannelQuality = {
+    rating: 'good' | 'fair' | 'poor';
+    flatline: boolean;
+    saturation: number;
+    std: number;
+    line_noise: number;
+};
+
+// A blink found by the pipeline's blink detection node
+export type BlinkEvent = {
+    timestamp: string;
+    // peak deflection in microvolts, negative for a trough
+    amplitude: number;
+    duration_secs: number;
+};
+
+// Power of one band of one channel over the latest window
+export type BandPower = {
+    band: string;
+    // microvolts²
+    absolute: number;
+    // share of the power across all bands
+    relative: number;
+};
+
 type Subscriber = (points: DataPoint[]) => void;
+type QualitySubscriber = (quality: ChannelQuality[]) => void;
+type BlinkSubscriber = (blinks: BlinkEvent[]) => void;
+// one array of bands per channel
+type BandPowerSubscriber = (power: BandPower[][]) => void;
 
 type WebSocketContextType = {
     subscribe: (fn: Subscriber) => () => void;
+    subscribeQuality: (fn: QualitySubscriber) => () => void;
+    subscribeBlinks: (fn: BlinkSubscriber) => () => void;
+    subscribeBandPower: (fn: BandPowerSubscriber) => () => void;
     sendPipelinePayload: (payload: PipelinePayload) => void;
+    reconfigureWindow: (config: WindowConfig) => void;
+    updatePipeline: (nodes: PipelineNode[]) => void;
+    updateNode: (node: PipelineNode) => void;
 };
 
 const WebSocketContext = createContext<WebSocketContextType | undefined>(
@@ -44,17 +80,36 @@ function formatTimestamp(raw: unknown): string {
 interface WebSocketBatch {
     timestamps: unknown[];
     signals: unknown[][];
+    // bandpassed copy of signals, empty when the pipeline doesn't filter
+    filtered?: unknown[][];
+    // seconds the filtered samples lag their timestamps by
+    filter_delay_secs?: number;
+    // leading samples already sent with the previous window
+    overlap_len?: number;
+    quality?: ChannelQuality[];
+    blinks?: BlinkEvent[];
+    band_power?: BandPower[][];
 }
 
 function normalizeBatch(batch: WebSocketBatch): DataPoint[] {
-    return batch.timestamps.map((time: unknown, i: number) => ({
-        time: formatTimestamp(time),
-        rawTime: String(time),
-        signal1: Number(batch.signals[0][i]) ?? 0,
-        signal2: Number(batch.signals[1][i]) ?? 0,
-        signal3: Number(batch.signals[2][i]) ?? 0,
-        signal4: Number(batch.signals[3][i]) ?? 0,
-    }));
+    const start = batch.overlap_len ?? 0;
+    const filtered = Boolean(batch.filtered?.length);
+    const signals = filtered ? batch.filtered! : batch.signals;
+    // filtered samples are plotted at the time they were measured
+    const delayMs = filtered ? (batch.filter_delay_secs ?? 0) * 1000 : 0;
+    return batch.timestamps.slice(start).map((stamp: unknown, j: number) => {
+        const i = start + j;
+        const time =
+            delayMs > 0 ? new Date(Date.parse(String(stamp)) - delayMs).toISOString() : stamp;
+        return {
+            time: formatTimestamp(time),
+            rawTime: String(time),
+            signal1: Number(signals[0][i]) ?? 0,
+            signal2: Number(signals[1][i]) ?? 0,
+            signal3: Number(signals[2][i]) ?? 0,
+            signal4: Number(signals[3][i]) ?? 0,
+        };
+    });
 }
 
 export function WebSocketProvider({ children }: { children: ReactNode }) {
@@ -62,6 +117,11 @@ export function WebSocketProvider({ children }: { children: ReactNode }) {
     const wsRef = useRef<WebSocket | null>(null);
     const pipelinePayloadRef = useRef<PipelinePayload | null>(null);
     const subscribersRef = useRef<Set<Subscriber>>(new Set());
+    const qualitySubscribersRef = useRef<Set<QualitySubscriber>>(new Set());
+    const blinkSubscribersRef = useRef<Set<BlinkSubscriber>>(new Set());
+    const bandPowerSubscribersRef = useRef<Set<BandPowerSubscriber>>(
+        new Set()
+    );
     const closingTimeoutRef = useRef<NodeJS.Timeout | null>(null);
     const isClosingGracefullyRef = useRef(false);
 
@@ -70,6 +130,21 @@ export function WebSocketProvider({ children }: { children: ReactNode }) {
         return () => subscribersRef.current.delete(fn);
     }, []);
 
+    const subscribeQuality = useCallback((fn: QualitySubscriber) => {
+        qualitySubscribersRef.current.add(fn);
+        return () => qualitySubscribersRef.current.delete(fn);
+    }, []);
+
+    const subscribeBlinks = useCallback((fn: BlinkSubscriber) => {
+        blinkSubscribersRef.current.add(fn);
+        return () => blinkSubscribersRef.current.delete(fn);
+    }, []);
+
+    const subscribeBandPower = useCallback((fn: BandPowerSubscriber) => {
+        bandPowerSubscribersRef.current.add(fn);
+        return () => bandPowerSubscribersRef.current.delete(fn);
+    }, []);
+
     const sendPipelinePayload = useCallback((payload: PipelinePayload) => {
         pipelinePayloadRef.current = payload;
         if (wsRef.current?.readyState === WebSocket.OPEN) {
@@ -78,6 +153,33 @@ export function WebSocketProvider({ children }: { children: ReactNode }) {
         }
     }, []);
 
+    // Changes chunk/overlap size of the running session; the server acknowledges or returns an error
+    const reconfigureWindow = useCallback((config: WindowConfig) => {
+        if (wsRef.current?.readyState === WebSocket.OPEN) {
+            wsRef.current.send(
+                JSON.stringify({ type: 'reconfigureWindow', config })
+            );
+        }
+    }, []);
+
+    // Replaces the running pipeline; the server switches to it at the next window
+    const updatePipeline = useCallback((nodes: PipelineNode[]) => {
+        if (wsRef.current?.readyState === WebSocket.OPEN) {
+            wsRef.current.send(
+                JSON.stringify({ type: 'updatePipeline', config: { nodes } })
+            );
+        }
+    }, []);
+
+    // Replaces the running node of the same type (or adds it)
+    const updateNode = useCallback((node: PipelineNode) => {
+        if (wsRef.current?.readyState === WebSocket.OPEN) {
+            wsRef.current.send(
+                JSON.stringify({ type: 'updateNode', config: node })
+            );
+        }
+    }, []);
+
     // Manage WebSocket lifecycle
     useEffect(() => {
         if (!dataStreaming) {
@@ -127,8 +229,43 @@ export function WebSocketProvider({ children }: { children: ReactNode }) {
                 isClosingGracefullyRef.current = false;
             } else {
                 try {
-                    const points = normalizeBatch(JSON.parse(message));
+                    const parsed = JSON.parse(message);
+                    if (typeof parsed?.error === 'string') {
+                        console.error('EEG stream error:', parsed.error);
+                        return;
+                    }
+                    if (parsed?.type === 'windowReconfigured') {
+                        console.log('Window reconfigured:', parsed.config);
+                        return;
+                    }
+                    if (parsed?.type === 'replayFinished') {
+                        console.log('Replay finished.');
+                        return;
+                    }
+                    if (parsed?.type === 'pipelineUpdated') {
+                        console.log('Pipeline updated:', parsed.config);
+                        return;
+                    }
+                    const points = normalizeBatch(parsed);
                     subscribersRef.current.forEach((fn) => fn(points));
+                    const quality: ChannelQuality[] = parsed.quality ?? [];
+                    if (quality.length > 0) {
+                        qualitySubscribersRef.current.forEach((fn) =>
+                            fn(quality)
+                        );
+                    }
+                    const blinks: BlinkEvent[] = parsed.blinks ?? [];
+                    if (blinks.length > 0) {
+                        blinkSubscribersRef.current.forEach((fn) =>
+                            fn(blinks)
+                        );
+                    }
+                    const bandPower: BandPower[][] = parsed.band_power ?? [];
+                    if (bandPower.length > 0) {
+                        bandPowerSubscribersRef.current.forEach((fn) =>
+                            fn(bandPower)
+                        );
+                    }
                 } catch (e) {
                     console.error('Failed to parse WebSocket message:', e);
                 }
@@ -168,7 +305,18 @@ export function WebSocketProvider({ children }: { children: ReactNode }) {
     }, [dataStreaming, activeSessionId]);
 
     return (
-        <WebSocketContext.Provider value={{ subscribe, sendPipelinePayload }}>
+        <WebSocketContext.Provider
+            value={{
+                subscribe,
+                subscribeQuality,
+                subscribeBlinks,
+                subscribeBandPower,
+                sendPipelinePayload,
+                reconfigureWindow,
+                updatePipeline,
+                updateNode,
+            }}
+        >
             {children}
         </WebSocketContext.Provider>
     );
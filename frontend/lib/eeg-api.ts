@@ -1,5 +1,18 @@
+export type ExportFormat = 'csv' | 'xdf' | 'edf' | 'brainvision';
+
+// BrainVision is three files (.vhdr/.vmrk/.eeg), delivered as one zip
+const EXPORT_EXTENSIONS: Record<ExportFormat, string> = {
+    csv: 'csv',
+    xdf: 'xdf',
+    edf: 'edf',
+    brainvision: 'zip',
+};
+
+// file formats the import endpoint reads, picked from the file extension
+export type ImportFormat = 'csv' | 'xdf' | 'edf';
+
 export type ExportOptions = {
-    format?: 'csv';
+    format?: ExportFormat;
     includeHeader?: boolean;
     start_time?: string; // RFC3339
     end_time?: string; // RFC3339
@@ -67,6 +80,65 @@ export function downloadCSV(csvContent: string, sessionId: number): void {
     URL.revokeObjectURL(url);
 }
 
+/**
+ * Request an EEG export in a binary format (XDF, EDF+, BrainVision) and return the file as a Blob.
+ */
+export async function exportEEGFile(
+    sessionId: number,
+    format: ExportFormat,
+    options: ExportOptions = {}
+): Promise<Blob> {
+    const timestamp = new Date().toISOString().replace(/[:.]/g, '-');
+    const body: ExportRequest = {
+        filename: `session_${sessionId}_${timestamp}.${EXPORT_EXTENSIONS[format]}`,
+        options: { includeHeader: true, ...options, format },
+    };
+
+    const response = await fetch(`/api/sessions/${sessionId}/eeg_data/export`, {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify(body),
+    });
+
+    if (!response.ok) {
+        let message = `Export failed (${response.status})`;
+        try {
+            const text = await response.text();
+            if (text) message = text;
+        } catch {}
+        throw new Error(message);
+    }
+
+    return response.blob();
+}
+
+/**
+ * Import an EEG recording file (CSV, XDF or EDF) into the given session.
+ */
+export async function importEEGFile(
+    sessionId: number,
+    file: File,
+    format: ImportFormat
+): Promise<void> {
+    const response = await fetch(
+        `/api/sessions/${sessionId}/eeg_data/import?format=${format}`,
+        {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/octet-stream' },
+            body: await file.arrayBuffer(),
+        }
+    );
+
+    if (!response.ok) {
+        let message = `Import failed (${response.status})`;
+        try {
+            const text = await response.text();
+            if (text) message = text;
+        } catch {}
+        throw new Error(message);
+    }
+}
+
 /**
  * Import EEG data from a raw CSV string into the given session.
  */
@@ -89,3 +161,54 @@ export async function importEEGData(
         throw new Error(message);
     }
 }
+
+export type WindowFunction = 'hann' | 'hamming' | 'rectangular';
+
+export type PsdOptions = {
+    start: string; // RFC3339
+    end: string; // RFC3339
+    channels?: number[]; // all channels when not given
+    segmentLen?: number; // samples per segment, 256 by default
+    overlap?: number; // samples, half a segment by default
+    window?: WindowFunction;
+};
+
+export type PowerSpectrum = {
+    start: string;
+    end: string;
+    sample_rate: number;
+    frequencies: number[]; // Hz
+    channels: { channel: number; power: number[] }[]; // µV²/Hz
+};
+
+/**
+ * Fetch the Welch power spectral density of a stretch of the given session.
+ */
+export async function getSessionPsd(
+    sessionId: number,
+    options: PsdOptions
+): Promise<PowerSpectrum> {
+    const params = new URLSearchParams({
+        start: options.start,
+        end: options.end,
+    });
+    if (options.channels) params.set('channels', options.channels.join(','));
+    if (options.segmentLen !== undefined)
+        params.set('segment_len', String(options.segmentLen));
+    if (options.overlap !== undefined)
+        params.set('overlap', String(options.overlap));
+    if (options.window) params.set('window', options.window);
+
+    const response = await fetch(`/api/sessions/${sessionId}/psd?${params}`);
+
+    if (!response.ok) {
+        let message = `PSD request failed (${response.status})`;
+        try {
+            const text = await response.text();
+            if (text) message = text;
+        } catch {}
+        throw new Error(message);
+    }
+
+    return response.json();
+}
@@ -1,9 +1,114 @@
+import { LabelColor } from '@/components/nodes/label-node/label-combo-box';
+
 export type PipelineNode = {
     type: string;
     config: Record<string, unknown>;
 };
 
+export type WindowConfig = {
+    chunk_size: number;
+    overlap_size: number;
+};
+
+// Config of a "blinkDetection" node; amplitudes in microvolts, channels are stream indices
+export type BlinkConfig = {
+    channels?: number[];
+    threshold?: number;
+    lowpass_hz?: number;
+    min_duration_secs?: number;
+    max_duration_secs?: number;
+    min_gap_secs?: number;
+    // store each blink as a time label of the session
+    store_labels?: boolean;
+    label?: string;
+    color?: LabelColor;
+};
+
+// Config of a "bandPower" node; bands cover low <= f < high (Hz), defaults to
+// delta/theta/alpha/beta/gamma
+export type BandPowerConfig = {
+    bands?: { name: string; low: number; high: number }[];
+};
+
+// Picks the LSL stream to record; every field that is set must match.
+// `predicate` is a raw XPath predicate that replaces the default type='EEG' filter.
+export type StreamSelector = {
+    name?: string;
+    source_id?: string;
+    hostname?: string;
+    predicate?: string;
+    resolve_timeout_secs?: number;
+    // seconds without samples before the stream is looked up again
+    lost_after_secs?: number;
+};
+
+// Plays back a stored session; without start/end the whole session is replayed
+export type ReplayConfig = {
+    session_id: number;
+    start?: string;
+    end?: string;
+    // 2 plays twice as fast as recorded
+    speed?: number;
+};
+
+// A simulated headset the backend publishes on LSL; amplitudes in microvolts
+export type MockSignalConfig = {
+    seed?: number;
+    pink_noise?: number;
+    alpha?: number;
+    beta?: number;
+    theta?: number;
+    line_noise?: number;
+    line_frequency?: number;
+    blinks_per_minute?: number;
+    blink_amplitude?: number;
+    muscle_bursts_per_minute?: number;
+    muscle_amplitude?: number;
+    states?: {
+        name: string;
+        duration_secs: number;
+        alpha: number;
+        beta: number;
+        theta: number;
+    }[];
+};
+
+export type MockConfig = {
+    n_channels?: number;
+    sample_rate?: number;
+    // samples per push
+    chunk_size?: number;
+    name?: string;
+    signal?: MockSignalConfig;
+};
+
+// Where the session's samples come from; omitted means the server's EEG_SOURCE default
+export type SourceConfig =
+    | { type: 'lsl'; config: StreamSelector }
+    | { type: 'replay'; config: ReplayConfig }
+    | { type: 'mock'; config: MockConfig };
+
+// LSL outlets the backend republishes the session on ("<name>-filtered", "<name>-ml")
+export type OutletConfig = {
+    signal?: boolean;
+    ml?: boolean;
+    name?: string;
+};
+
+// An LSL marker stream (e.g. PsychoPy) whose events are stored as time labels while recording;
+// without a predicate the first type='Markers' stream is used
+export type MarkerConfig = {
+    stream?: StreamSelector;
+    // marker text -> label color
+    colors?: Record<string, LabelColor>;
+    default_color?: LabelColor;
+};
+
 export type PipelinePayload = {
     session_id: string;
     nodes: PipelineNode[];
+    source?: SourceConfig;
+    stream?: StreamSelector;
+    outlets?: OutletConfig;
+    markers?: MarkerConfig;
 };
@@ -103,10 +103,8 @@ export type TimeLabel = {
 
 export type EegDataRow = {
     time: string;
-    channel1: number;
-    channel2: number;
-    channel3: number;
-    channel4: number;
+    // one value per channel, in channel order
+    channels: number[];
 };
 
 export async function saveTimeLabels(